@@ -7,12 +7,12 @@ pub struct Args {
     #[command(subcommand)]
     pub action: Action,
 
-    /// Where it should connect
+    /// Where it should connect, it is mandatory except if a local file is validated
     /// Allowed formats:
     /// - <protocol>://<hostname>:<port>, for example http://127.0.0.1:3041
     /// - cfg://<definition-name>, for example: cfg://atihome, it will search  or hostname and CA certificate
     #[arg(short = 'H', long, verbatim_doc_comment, value_parser = check_hostname)]
-    pub hostname: String,
+    pub hostname: Option<String>,
 
     /// Config file for connection details
     #[arg(short, long, default_value_t = String::from("/etc/olympus/hephaestus/client.conf"))]
@@ -46,6 +46,21 @@ pub enum Action {
         set: String,
     },
 
+    /// Check a plan and print every problem of it, a local file can be checked without server
+    Validate {
+        /// Plan file on this host, it is checked without connecting to server
+        #[arg(long, conflicts_with_all = ["set", "name"])]
+        file: Option<String>,
+
+        /// Specified plen set's name on server
+        #[arg(long, requires = "name")]
+        set: Option<String>,
+
+        /// Specified plan's name on server
+        #[arg(long, requires = "set")]
+        name: Option<String>,
+    },
+
     /// List the scheduled plan output from memory
     Plans,
 
@@ -54,6 +69,18 @@ pub enum Action {
         /// Scheduled plan id
         #[arg(long)]
         id: u32,
+
+        /// Keep printing the new lines until plan is running
+        #[arg(short, long, default_value_t = false)]
+        follow: bool,
+
+        /// Print the status of every step after the log
+        #[arg(short, long, default_value_t = false)]
+        steps: bool,
+
+        /// Print the content of plan file, which was executed
+        #[arg(long, default_value_t = false)]
+        snapshot: bool,
     },
 
     /// Execute a specified plan
@@ -65,6 +92,60 @@ pub enum Action {
         /// Specified plen set's name
         #[arg(long)]
         set: String,
+
+        /// Parameter of plan in name=value format, it can be specified more times
+        #[arg(long = "param", value_parser = parse_param)]
+        params: Vec<(String, String)>,
+
+        /// Do not run anything, just print which steps would run in which order with their resolved commands
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Assumed result of a step in dry run, in step=status format (ok, nok, failed or timeout), it can be specified more times
+        #[arg(long, value_parser = parse_param, requires = "dry_run")]
+        assume: Vec<(String, String)>,
+    },
+
+    /// Run an ended plan again, from a step or from its failed steps
+    Rerun {
+        /// Id of the original run
+        #[arg(long)]
+        id: u32,
+
+        /// Step from where plan is run again, earlier steps keep their original status
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Which steps are run again: from (specified step and its dependants) or failed (steps which were not OK).
+        /// Default is from if step is specified, otherwise failed
+        #[arg(long)]
+        mode: Option<String>,
+    },
+
+    /// Cancel a running plan
+    Cancel {
+        /// Scheduled plan id
+        #[arg(long)]
+        id: u32,
+    },
+
+    /// Approve or reject a step which is waiting for approval
+    Approve {
+        /// Scheduled plan id
+        #[arg(long)]
+        id: u32,
+
+        /// Name of approval step
+        #[arg(long)]
+        step: String,
+
+        /// Reject the step instead of approve it
+        #[arg(long, default_value_t = false)]
+        reject: bool,
+
+        /// Comment of decision, it is recorded in the history of run
+        #[arg(long)]
+        comment: Option<String>,
     },
 
     /// Write a specific scheduled plan output into file
@@ -76,6 +157,42 @@ pub enum Action {
 
     /// Write all scheduled plan output into files
     DumpAllHistory,
+
+    /// List archived plan outputs
+    Archives {
+        /// Name of plan set
+        #[arg(long)]
+        set: Option<String>,
+
+        /// Name of plan
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Archived at or after this date, YYYY-MM-DD or RFC 3339 time
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Archived at or before this date, YYYY-MM-DD or RFC 3339 time
+        #[arg(long)]
+        to: Option<String>,
+    },
+
+    /// Print an archived plan output
+    Archive {
+        /// Name of archive file, as it is listed by archives command
+        #[arg(long)]
+        file: String,
+    },
+
+    /// List scheduled plans with their next run
+    Schedules,
+}
+
+fn parse_param(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((String::from(name), String::from(value))),
+        _ => Err(String::from("Parameter must be in name=value format")),
+    }
 }
 
 fn check_hostname(s: &str) -> Result<String, String> {
@@ -1,10 +1,12 @@
 use clap::Parser;
-use tonic::transport::{Channel, Certificate, ClientTlsConfig};
-use tonic::{Request, Response, Status};
+use tonic::transport::{Channel, Certificate, ClientTlsConfig, Identity};
+use tonic::{Request, Response, Status, Streaming};
 use std::process::exit;
+use std::collections::HashMap;
+use std::path::Path;
 
 use hephaestus::hephaestus_client::HephaestusClient;
-use hephaestus::{Empty, List, PlanSetArg, PlanArg, PlanId, PlanDetails, PlanHistory, PlanList};
+use hephaestus::{Empty, List, PlanSetArg, PlanArg, PlanId, PlanDetails, PlanList, RunStatus, ArchiveFilter, ArchiveList, ArchiveName, ArchiveContent, ScheduleList, RerunArg, ApprovalArg, DryRunArg, DryRunResult, ValidationResult};
 
 mod hephaestus {
     tonic::include_proto!("hephaestus");
@@ -33,6 +35,20 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
     // Measure runtime of script
     let start = std::time::Instant::now();
 
+    // Local file is validated on this host, server is not needed
+    if let Action::Validate { file: Some(ref file), .. } = args.action {
+        let problems = hephaestus_plan::lint::lint_file(Path::new(file));
+        let rc = print_problems(&problems);
+
+        print_verbose(&args, format!("Elapsed time: {:?}", start.elapsed()));
+        return Ok(rc);
+    }
+
+    if args.hostname.is_none() {
+        eprintln!("Hostname must be specified by -H option");
+        return Ok(4);
+    }
+
     // Try to connect to gRPC server
     let grpc_channel = create_grpc_channel(args.clone()).await;
 
@@ -95,6 +111,7 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
             let params = PlanArg {
                 set: set.clone(),
                 plan: name.clone(),
+                params: HashMap::new(),
             };
             let response: Result<Response<PlanDetails>, Status> = grpc_client.list_plan(params).await;
 
@@ -104,6 +121,19 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
 
                     println!("Details about {}/{} plan:", set.clone(), plan.id);
 
+                    if plan.params.len() > 0 {
+                        println!("Parameters:");
+                        for param in plan.params {
+                            if param.required {
+                                println!("- {} ({}), mandatory", param.name, param.r#type);
+                            }
+                            else {
+                                println!("- {} ({}), default: {}", param.name, param.r#type, param.default);
+                            }
+                        }
+                        println!("");
+                    }
+
                     for step in plan.steps {
                         println!("{} - {}", step.name, step.desc);
                         println!("- Type:                  {}", step.r#type);
@@ -112,10 +142,26 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
                             println!("- Assigned user:       {}", step.user);
                         }
                         
-                        println!("- Command:               {}", step.action);
+                        // Command can be a multi-line script, continuation lines are aligned under the first one
+                        let mut lines = step.action.lines();
+                        println!("- Command:               {}", lines.next().unwrap_or(""));
+                        for line in lines {
+                            println!("                         {}", line);
+                        }
 
                         if !step.parent.is_empty() {
-                            println!("- Depend from:         {}", step.parent);
+                            println!("- Depend from:         {}", step.parent.replace(",", ", "));
+                            if step.parent.contains(',') {
+                                println!("- Join mode:           {}", step.join);
+                            }
+                        }
+
+                        if !step.timeout.is_empty() {
+                            println!("- Timeout:               {}", step.timeout);
+                        }
+
+                        if step.retry > 0 {
+                            println!("- Retry:                 {} times, delay {}, backoff x{}", step.retry, step.retry_delay, step.retry_backoff);
                         }
 
                         if step.envvars.len() > 0 {
@@ -144,11 +190,20 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
             match response {
                 Ok(resp) => {
                     let plans = resp.into_inner();
-                    let mut plans = plans.ids;
-                    plans.sort_by(|a, b| a.id.cmp(&b.id));
-
-                    for plan_id in plans {
-                        println!("{}", plan_id.id);
+                    let mut runs = plans.runs;
+                    runs.sort_by_key(|run| run.id.as_ref().map_or(0, |id| id.id));
+
+                    println!("{:<6} {:<36} {:<30} {:<12} {:<29} {:>10}  {}", "ID", "UUID", "Plan", "Status", "Started", "Duration", "Requester");
+                    for run in runs {
+                        let id = run.id.unwrap_or_default();
+                        println!("{:<6} {:<36} {:<30} {:<12} {:<29} {:>9.1}s  {}",
+                            id.id,
+                            id.uuid,
+                            format!("{}/{}", id.set, id.plan),
+                            run.status,
+                            run.start_time,
+                            run.duration_ms as f64 / 1000.0,
+                            run.requester);
                     }
                 },
                 Err(e) => {
@@ -158,20 +213,90 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
             }
         },
         /*---------------------------------------------------------------------------------------*/
+        /* Validate a plan on server                                                             */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Validate { ref set, ref name, .. } => {
+            let (set, name) = match (set, name) {
+                (Some(set), Some(name)) => (set.clone(), name.clone()),
+                _ => {
+                    eprintln!("Either --file or --set and --name must be specified");
+                    return Ok(4);
+                }
+            };
+
+            let params = PlanArg {
+                set: set,
+                plan: name,
+                params: HashMap::new(),
+            };
+            let response: Result<Response<ValidationResult>, Status> = grpc_client.validate_plan(params).await;
+
+            match response {
+                Ok(resp) => final_rc = print_problems(&resp.into_inner().problems),
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
+        /* Dry run of specified plan, nothing is started                                         */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Exec { ref set, ref name, ref params, dry_run, ref assume } if dry_run => {
+            let params = DryRunArg {
+                set: set.clone(),
+                plan: name.clone(),
+                params: params.iter().cloned().collect(),
+                assume: assume.iter().cloned().collect(),
+            };
+            let response: Result<Response<DryRunResult>, Status> = grpc_client.dry_run(params).await;
+
+            match response {
+                Ok(resp) => print_dry_run(set, name, &resp.into_inner()),
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
         /* Execute specified plan                                                                */
         /*---------------------------------------------------------------------------------------*/
-        Action::Exec { ref set, ref name } => {
+        Action::Exec { ref set, ref name, ref params, .. } => {
             let params = PlanArg {
                 set: set.clone(),
                 plan: name.clone(),
+                params: params.iter().cloned().collect(),
             };
             let response: Result<Response<PlanId>, Status> = grpc_client.execute(params).await;
 
+            match response {
+                Ok(resp) => {
+                    let plan_id = resp.into_inner();
+                    println!("Batch is started, id: {}, uuid: {}", plan_id.id, plan_id.uuid);
+                }
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
+        /* Run an ended plan again                                                               */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Rerun { id, ref from, ref mode } => {
+            let params = RerunArg {
+                id: id,
+                from_step: from.clone().unwrap_or_default(),
+                mode: mode.clone().unwrap_or_default(),
+            };
+            let response: Result<Response<PlanId>, Status> = grpc_client.rerun(params).await;
+
             match response {
                 Ok(resp) => {
                     let plan_id = resp.into_inner();
 
-                    println!("Batch is started, id: {}", plan_id.id);
+                    println!("Batch is started, id: {}, uuid: {}", plan_id.id, plan_id.uuid);
                 }
                 Err(e) => {
                     eprintln!("Failed request: {}", e.message());
@@ -182,21 +307,75 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
         /*---------------------------------------------------------------------------------------*/
         /* Get output of a specified online log                                                  */
         /*---------------------------------------------------------------------------------------*/
-        Action::Status { id } => {
+        Action::Status { id, follow, steps, snapshot } if follow => {
+            let params = PlanId {
+                id: id,
+                set: String::new(),
+                plan: String::new(),
+                uuid: String::new(),
+            };
+            let response: Result<Response<Streaming<RunStatus>>, Status> = grpc_client.follow_status(params).await;
+
+            match response {
+                Ok(resp) => {
+                    let mut stream = resp.into_inner();
+                    let mut last: Option<RunStatus> = None;
+
+                    loop {
+                        match stream.message().await {
+                            Ok(Some(status)) => {
+                                for line in &status.history {
+                                    println!("{}", line);
+                                }
+                                last = Some(status);
+                            },
+                            Ok(None) => break,
+                            Err(e) => {
+                                eprintln!("Connection is broken: {}", e.message());
+                                final_rc = 4;
+                                break;
+                            }
+                        }
+                    }
+
+                    if let Some(status) = last {
+                        if steps {
+                            print_steps(&status);
+                        }
+                        if snapshot {
+                            print_snapshot(&status);
+                        }
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        Action::Status { id, steps, snapshot, .. } => {
             let params = PlanId {
                 id: id,
                 set: String::new(),
                 plan: String::new(),
+                uuid: String::new(),
             };
-            let response: Result<Response<PlanHistory>, Status> = grpc_client.show_status(params).await;
+            let response: Result<Response<RunStatus>, Status> = grpc_client.show_status(params).await;
 
             match response {
                 Ok(resp) => {
-                    let hist = resp.into_inner();
+                    let status = resp.into_inner();
 
-                    for line in hist.history {
+                    for line in &status.history {
                         println!("{}", line);
                     }
+
+                    if steps {
+                        print_steps(&status);
+                    }
+                    if snapshot {
+                        print_snapshot(&status);
+                    }
                 },
                 Err(e) => {
                     eprintln!("Failed request: {}", e.message());
@@ -205,6 +384,45 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
             }
         },
         /*---------------------------------------------------------------------------------------*/
+        /* Cancel a running plan                                                                 */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Cancel { id } => {
+            let params = PlanId {
+                id: id,
+                set: String::new(),
+                plan: String::new(),
+                uuid: String::new(),
+            };
+            let response: Result<Response<Empty>, Status> = grpc_client.cancel(params).await;
+            match response {
+                Ok(_) => println!("Cancel is requested"),
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
+        /* Approve or reject a step which is waiting for approval                                */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Approve { id, ref step, reject, ref comment } => {
+            let params = ApprovalArg {
+                id: id,
+                step: step.clone(),
+                reject: reject,
+                comment: comment.clone().unwrap_or_default(),
+            };
+            let response: Result<Response<Empty>, Status> = grpc_client.approve(params).await;
+            match response {
+                Ok(_) if reject => println!("Step is rejected"),
+                Ok(_) => println!("Step is approved"),
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
         /* Write all log from memory into files                                                  */
         /*---------------------------------------------------------------------------------------*/
         Action::DumpAllHistory => {
@@ -221,6 +439,7 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
                 id: id,
                 set: String::new(),
                 plan: String::new(),
+                uuid: String::new(),
             };
             let response: Result<Response<Empty>, Status> = grpc_client.dump_hist(params).await;
             match response {
@@ -231,6 +450,79 @@ async fn main_async() -> Result<i32, Box<dyn std::error::Error>> {
                 }
             }
         },
+        /*---------------------------------------------------------------------------------------*/
+        /* List archived outputs                                                                 */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Archives { ref set, ref name, ref from, ref to } => {
+            let params = ArchiveFilter {
+                set: set.clone().unwrap_or_default(),
+                plan: name.clone().unwrap_or_default(),
+                from: from.clone().unwrap_or_default(),
+                to: to.clone().unwrap_or_default(),
+            };
+            let response: Result<Response<ArchiveList>, Status> = grpc_client.list_archives(params).await;
+            match response {
+                Ok(resp) => {
+                    let archives = resp.into_inner().archives;
+
+                    println!("{:<29} {:>10}  {}", "Archived", "Size", "File");
+                    for archive in archives {
+                        println!("{:<29} {:>10}  {}", archive.archived, archive.size, archive.file);
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
+        /* Print an archived output                                                              */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Archive { ref file } => {
+            let params = ArchiveName {
+                file: file.clone(),
+            };
+            let response: Result<Response<ArchiveContent>, Status> = grpc_client.get_archive(params).await;
+            match response {
+                Ok(resp) => print!("{}", resp.into_inner().content),
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
+        /*---------------------------------------------------------------------------------------*/
+        /* List scheduled plans                                                                  */
+        /*---------------------------------------------------------------------------------------*/
+        Action::Schedules => {
+            let response: Result<Response<ScheduleList>, Status> = grpc_client.show_schedules(Empty {}).await;
+            match response {
+                Ok(resp) => {
+                    let schedules = resp.into_inner().schedules;
+
+                    println!("{:<30} {:<16} {:<20} {:<7} {:<29}", "Plan", "Schedule", "Timezone", "Overlap", "Next run");
+                    for schedule in schedules {
+                        let next_run = match (schedule.next_run.is_empty(), schedule.queued) {
+                            (_, true) => format!("{} (queued)", schedule.next_run),
+                            (true, false) => String::from("never"),
+                            (false, false) => schedule.next_run,
+                        };
+
+                        println!("{:<30} {:<16} {:<20} {:<7} {:<29}",
+                            format!("{}/{}", schedule.set, schedule.plan),
+                            schedule.schedule,
+                            schedule.timezone,
+                            schedule.overlap,
+                            next_run);
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Failed request: {}", e.message());
+                    final_rc = 4;
+                }
+            }
+        },
     }
 
     let elapsed = start.elapsed();
@@ -246,18 +538,129 @@ fn print_verbose<T: std::fmt::Display>(args: &Args, text: T) {
     }
 }
 
+/// Print the summary of run and its steps
+fn print_steps(status: &RunStatus) {
+    println!();
+    println!("Status: {}, started: {}, ended: {}, duration: {:.1}s",
+        status.status,
+        status.start_time,
+        if status.end_time.is_empty() { "-" } else { &status.end_time },
+        status.duration_ms as f64 / 1000.0);
+    println!("Requested by: {}", status.requester);
+    println!("Plan SHA-256: {}", status.plan_hash);
+    if status.rerun_of != 0 {
+        println!("Rerun of: {}", status.rerun_of);
+    }
+    if !status.params.is_empty() {
+        let mut params: Vec<String> = status.params.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        params.sort();
+        println!("Parameters: {}", params.join(", "));
+    }
+
+    println!("{:<20} {:<10} {:<15} {:>9} {:>7} {:>10}", "Step", "Type", "Status", "Exit code", "Attempt", "Duration");
+    for step in &status.steps {
+        let exit_code = match step.exit_code {
+            -1 => String::from("-"),
+            code => code.to_string(),
+        };
+        println!("{:<20} {:<10} {:<15} {:>9} {:>7} {:>9.1}s", step.name, step.r#type, step.status, exit_code, step.attempt, step.duration_ms as f64 / 1000.0);
+    }
+
+    for approval in &status.approvals {
+        let time = approval.time.get(..19).unwrap_or(&approval.time).replace("T", " ");
+        let decision = if approval.approved { "approved" } else { "rejected" };
+
+        match approval.comment.is_empty() {
+            true => println!("Step {} is {} by {} at {}", approval.step, decision, approval.by, time),
+            false => println!("Step {} is {} by {} at {}: {}", approval.step, decision, approval.by, time, approval.comment),
+        }
+    }
+}
+
+/// Print the steps of dry run in the order of their waves, with their resolved commands
+fn print_dry_run(set: &str, plan: &str, result: &DryRunResult) {
+    println!("Dry run of {}/{}, expected overall status: {}", set, plan, result.status);
+    println!();
+
+    let mut steps: Vec<_> = result.steps.iter().collect();
+    // Steps which would not run are listed at the end
+    steps.sort_by_key(|step| if step.wave == 0 { u32::MAX } else { step.wave });
+
+    println!("{:<5} {:<20} {:<10} {}", "Wave", "Step", "Type", "Status");
+    for step in steps {
+        let wave = match step.wave {
+            0 => String::from("-"),
+            wave => wave.to_string(),
+        };
+        let status = match step.assumed {
+            true => format!("{} (assumed)", step.status),
+            false => step.status.clone(),
+        };
+        println!("{:<5} {:<20} {:<10} {}", wave, step.name, step.r#type, status);
+
+        if step.wave == 0 {
+            continue;
+        }
+
+        if !step.parents.is_empty() {
+            println!("      parents: {}", step.parents.join(", "));
+        }
+        if !step.user.is_empty() {
+            println!("      user: {}", step.user);
+        }
+        if !step.cwd.is_empty() {
+            println!("      cwd: {}", step.cwd);
+        }
+        let mut env: Vec<String> = step.env.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        env.sort();
+        for var in env {
+            println!("      env: {}", var);
+        }
+        for line in step.command.lines() {
+            println!("      | {}", line);
+        }
+    }
+}
+
+/// Print the problems of plan validation, return code is 8 if plan has any problem
+fn print_problems(problems: &[String]) -> i32 {
+    if problems.is_empty() {
+        println!("Plan is valid");
+        return 0;
+    }
+
+    for problem in problems {
+        println!("{}", problem);
+    }
+    println!("Plan has {} problem(s)", problems.len());
+
+    return 8;
+}
+
+/// Print the plan file content, which was executed in the run
+fn print_snapshot(status: &RunStatus) {
+    println!();
+    println!("Plan snapshot (SHA-256: {}):", status.plan_hash);
+    print!("{}", status.plan_snapshot);
+    if !status.plan_snapshot.ends_with('\n') {
+        println!();
+    }
+}
+
 /// Create a new gRPC channel which connection to Hephaestus
 async fn create_grpc_channel(args: Args) -> Channel {
-    if !args.hostname.starts_with("cfg://") {
+    let hostname = args.hostname.clone().unwrap_or_default();
+
+    if !hostname.starts_with("cfg://") {
         print_verbose(&args, "Not cfg:// procotll is given");
-        return Channel::from_shared(args.hostname.clone())
+        return Channel::from_shared(hostname)
             .unwrap()
             .connect()
             .await
             .unwrap();
     }
 
-    let host = args.hostname[6..].to_string();
+    let host = hostname[6..].to_string();
 
     print_verbose(&args, format!("cfg:// is specified, will be looking for in {} for {} settings", host, args.config));
 
@@ -292,10 +695,32 @@ async fn create_grpc_channel(args: Args) -> Channel {
         };
         let ca = Certificate::from_pem(pem);
 
-        let tls = ClientTlsConfig::new()
+        let mut tls = ClientTlsConfig::new()
             .ca_certificate(ca)
             .domain_name(domain.unwrap());
-        
+
+        // Client certificate is needed if server verifies its clients
+        let client_pem = config.get(&format!("node.{}.client_pem", host));
+        let client_key = config.get(&format!("node.{}.client_key", host));
+
+        if let (Some(client_pem), Some(client_key)) = (client_pem, client_key) {
+            let cert = match tokio::fs::read(client_pem).await {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", client_pem, e);
+                    std::process::exit(2);
+                }
+            };
+            let key = match tokio::fs::read(client_key).await {
+                Ok(k) => k,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", client_key, e);
+                    std::process::exit(2);
+                }
+            };
+            tls = tls.identity(Identity::from_pem(cert, key));
+        }
+
         return Channel::from_shared(addr)
             .unwrap()
             .tls_config(tls)
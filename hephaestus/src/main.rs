@@ -2,9 +2,12 @@ use std::env;
 use std::fs;
 use std::path::Path;
 use std::process::exit;
+use std::sync::Arc;
+use std::sync::Condvar;
 use std::sync::Mutex;
 use std::sync::RwLock;
-use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::collections::{BTreeMap, HashMap};
 
 use tokio::sync::{mpsc::Sender};
 
@@ -12,8 +15,16 @@ mod services;
 mod structs;
 
 static GLOBAL_CONFIG: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
-static HISTORY: RwLock<Option<HashMap<structs::historey_key::HistoryKey, Vec<String>>>> = RwLock::new(None);
+static HISTORY: RwLock<Option<HashMap<structs::historey_key::HistoryKey, structs::run::RunRecord>>> = RwLock::new(None);
 static HERMES_TX: Mutex<Option<Sender<(String, String)>>> = Mutex::new(None);
+static STEP_SLOTS: services::executor::StepSlots = services::executor::StepSlots::new();
+static RUNNING_PLANS: Mutex<BTreeMap<u32, Arc<AtomicBool>>> = Mutex::new(BTreeMap::new());
+static LAST_RUN_ID: Mutex<u32> = Mutex::new(0);
+static PLAN_LOCKS: Mutex<services::locks::LockTable> = Mutex::new(services::locks::LockTable::new());
+static PLAN_LOCKS_CV: Condvar = Condvar::new();
+static SCHEDULES: Mutex<Vec<hephaestus_plan::structs::schedule::ScheduledPlan>> = Mutex::new(Vec::new());
+static APPROVALS: Mutex<BTreeMap<(u32, String), std::sync::mpsc::Sender<bool>>> = Mutex::new(BTreeMap::new());
+static JOURNALS: Mutex<BTreeMap<u32, Arc<Mutex<Option<fs::File>>>>> = Mutex::new(BTreeMap::new());
 static VERSION: &str = "v.0.2.0";
 
 fn main() {
@@ -51,6 +62,7 @@ fn main() {
     /* <work_dir>                                                                                */
     /* +-- plans                                                                                 */
     /* '-- logs                                                                                  */
+    /*     '-- journal                                                                           */
     /*-------------------------------------------------------------------------------------------*/
     if let Some(plan_dir) = config.get("plan.rule_dir") {
         let plan_dir = Path::new(&plan_dir);
@@ -72,18 +84,69 @@ fn main() {
                 exit(1);
             }
         }
+
+        let journal_dir = log_dir.join("journal");
+
+        if !journal_dir.is_dir() {
+            if let Err(e) = fs::create_dir(&journal_dir) {
+                println!("Failed to create journal directory: {:?}", e);
+                exit(1);
+            }
+        }
     }
 
     println!("Directory check is OK");
 
     /*-------------------------------------------------------------------------------------------*/
-    /* Initailize history                                                                        */
+    /* Initailize history, runs of previous server instance are read from the journals           */
     /*-------------------------------------------------------------------------------------------*/
     {
+        let loaded = services::history::load();
+        println!("History is loaded, {} run(s) found", loaded.len());
+
+        // Run IDs are continued from the persisted sequence, so they are never reused
+        let max_loaded = loaded.keys().map(|key| key.id).max().unwrap_or(0);
+        let sequence = match services::history::load_sequence() {
+            Ok(id) => id,
+            Err(e) => {
+                println!("Last run ID cannot be determined: {}", e);
+                exit(1);
+            }
+        };
+
+        let mut last_id = LAST_RUN_ID.lock().unwrap();
+        *last_id = std::cmp::max(sequence, max_loaded);
+        println!("Last run ID is {}", *last_id);
+
         let mut history = HISTORY.write().unwrap();
-        *history = Some(HashMap::new());
+        *history = Some(loaded);
     }
 
+    /*-------------------------------------------------------------------------------------------*/
+    /* Start archival of finished runs, if retention policy is specified                         */
+    /*-------------------------------------------------------------------------------------------*/
+    match services::archive::Policy::from_config(&config) {
+        Ok(Some(policy)) => {
+            println!("Retention policy is set, it is checked in every {:?}", policy.interval);
+            std::thread::spawn(move || {
+                loop {
+                    policy.apply();
+                    std::thread::sleep(policy.interval);
+                }
+            });
+        },
+        Ok(None) => (),
+        Err(e) => {
+            println!("{}", e);
+            exit(1);
+        }
+    }
+
+    /*-------------------------------------------------------------------------------------------*/
+    /* Start scheduler, it starts the plans which have schedule                                  */
+    /*-------------------------------------------------------------------------------------------*/
+    std::thread::spawn(|| services::scheduler::run());
+
     /*-------------------------------------------------------------------------------------------*/
     /* Allocate a tokio runtime and start Hermes client if required                              */
     /*-------------------------------------------------------------------------------------------*/
@@ -0,0 +1,141 @@
+//! Approval steps
+//!
+//! An approval step does not run any command, it waits until someone approves or rejects it with the
+//! `Approve` endpoint. Approved step is completed as `Ok`, rejected one as `Nok`. If the step has timeout
+//! and nobody decides until it expires, the step is `TimedOut`. Decisions are recorded in the run's history.
+
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use hephaestus_plan::structs::enums::{StepOutputType, StepStatus};
+use hephaestus_plan::structs::step::Step;
+
+use crate::structs::historey_key::HistoryKey;
+use crate::structs::run::{ApprovalRecord, LogEntry};
+
+use crate::APPROVALS;
+use crate::HISTORY;
+
+use super::history::{write_approval, write_entry};
+
+/// How often a waiting step checks that plan has been cancelled or its deadline has expired
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reasons why a decision could not be made
+/// - Internal => Something is wrong on server side
+/// - NotFound => Run or step does not exist
+/// - NotWaiting => Step exists, but it is not waiting for approval, e.g.: it has been decided already
+pub enum ApprovalError {
+    Internal(String),
+    NotFound(String),
+    NotWaiting(String),
+}
+
+impl fmt::Display for ApprovalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApprovalError::Internal(e) => write!(f, "{}", e),
+            ApprovalError::NotFound(e) => write!(f, "{}", e),
+            ApprovalError::NotWaiting(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Wait for the decision about an approval step, then set the step status by it
+pub fn wait(id: u32, step: &mut Step, deadline: Option<Instant>, cancel: &AtomicBool) {
+    let name = step.step_name.clone();
+    let message = |text: &str, out_type: StepOutputType| {
+        write_entry(id, LogEntry::new(&name, out_type, String::from(text)));
+    };
+
+    step.attempt = 1;
+    step.exit_code = None;
+
+    let deadline = match (step.timeout, deadline) {
+        (Some(timeout), Some(deadline)) => Some(std::cmp::min(Instant::now() + timeout, deadline)),
+        (Some(timeout), None) => Some(Instant::now() + timeout),
+        (None, deadline) => deadline,
+    };
+
+    let (tx, rx) = mpsc::channel::<bool>();
+    APPROVALS.lock().unwrap().insert((id, name.clone()), tx);
+
+    message("----> Waiting for approval", StepOutputType::Info);
+
+    let stopped = loop {
+        if let Ok(approved) = rx.recv_timeout(CHECK_INTERVAL) {
+            break if approved { StepStatus::Ok } else { StepStatus::Nok };
+        }
+
+        let reason = if cancel.load(Ordering::SeqCst) {
+            StepStatus::Cancelled
+        }
+        else if deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+            StepStatus::TimedOut
+        }
+        else {
+            continue;
+        };
+
+        // Decision may have arrived meanwhile, it is taken if the step is already removed
+        if APPROVALS.lock().unwrap().remove(&(id, name.clone())).is_none() {
+            if let Ok(approved) = rx.try_recv() {
+                break if approved { StepStatus::Ok } else { StepStatus::Nok };
+            }
+        }
+
+        match reason {
+            StepStatus::Cancelled => message("----> Approval is stopped because plan has been cancelled", StepOutputType::Error),
+            _ => message("----> Nobody has decided about the approval before deadline", StepOutputType::Error),
+        }
+        break reason;
+    };
+
+    step.status = stopped;
+}
+
+/// Approve or reject a step which is waiting for approval
+pub fn decide(id: u32, step: &str, approved: bool, by: String, comment: String) -> Result<(), ApprovalError> {
+    let mut approvals = APPROVALS.lock().unwrap();
+
+    let tx = match approvals.remove(&(id, String::from(step))) {
+        Some(tx) => tx,
+        None => {
+            let history = HISTORY.read().unwrap();
+            let history = match &*history {
+                Some(h) => h,
+                None => return Err(ApprovalError::Internal(String::from("History is not initialized yet"))),
+            };
+
+            let key = HistoryKey { id: id, set: String::new(), plan: String::new(), uuid: String::new() };
+            return match history.get(&key) {
+                None => Err(ApprovalError::NotFound(String::from("Id is not found"))),
+                Some(run) if !run.steps.iter().any(|s| s.name == step) => Err(ApprovalError::NotFound(format!("Step is not found: {}", step))),
+                Some(_) => Err(ApprovalError::NotWaiting(format!("Step is not waiting for approval: {}", step))),
+            };
+        }
+    };
+
+    let text = match comment.is_empty() {
+        true => format!("----> {} by {}", if approved { "Approved" } else { "Rejected" }, by),
+        false => format!("----> {} by {}: {}", if approved { "Approved" } else { "Rejected" }, by, comment),
+    };
+
+    write_approval(id, ApprovalRecord {
+        step: String::from(step),
+        approved: approved,
+        by: by,
+        time: Local::now(),
+        comment: comment,
+    });
+    write_entry(id, LogEntry::new(step, StepOutputType::Info, text));
+
+    // Step is removed from the waiting ones while the lock is held, so the waiting thread always reads this
+    let _ = tx.send(approved);
+
+    return Ok(());
+}
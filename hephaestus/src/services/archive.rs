@@ -0,0 +1,459 @@
+//! Archived logs of runs
+//!
+//! When the history of a run is dumped, its log is written into `<plan.rule_log>/<set>.<plan>(<id>)_<epoch>.log`,
+//! where epoch is the time of archiving. Dots and percent signs within set and plan names are written as `%2E`
+//! and `%25`, so the dot between them can always be found. If `history.archive_gzip` is set, the file is compressed and `.gz` is
+//! appended to its name. This module writes, lists and reads these files, and it also applies the retention
+//! policy of `history.*` config settings: finished runs are archived and old archives are deleted.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use hephaestus_plan::parser::parse_duration;
+
+use crate::structs::historey_key::HistoryKey;
+use crate::structs::run::{parse_time, RunRecord};
+
+use crate::GLOBAL_CONFIG;
+use crate::HISTORY;
+
+use super::history::remove_run;
+
+/// Extension of archive files
+const ARCHIVE_EXT: &str = ".log";
+
+/// Extension which is appended to the compressed archive files
+const GZIP_EXT: &str = ".gz";
+
+/// Default time between two checks of retention policy
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Retention policy of run history, it is read from `history.*` config settings
+/// - archive_after => Finished runs are archived after this time
+/// - keep_runs => Only this many finished runs of a plan are kept in memory, older ones are archived
+/// - retention => Archive files are deleted after this time
+/// - interval => How often policy is applied
+pub struct Policy {
+    pub archive_after: Option<Duration>,
+    pub keep_runs: Option<usize>,
+    pub retention: Option<Duration>,
+    pub interval: Duration,
+}
+
+impl Policy {
+    /// Read policy from config, it is None if none of the retention settings is specified
+    pub fn from_config(config: &HashMap<String, String>) -> Result<Option<Policy>, String> {
+        let archive_after = match config.get("history.archive_after") {
+            Some(value) => Some(parse_duration(value).map_err(|e| format!("Invalid history.archive_after: {}", e))?),
+            None => None,
+        };
+
+        let keep_runs = match config.get("history.keep_runs_per_plan") {
+            Some(value) => match value.parse::<usize>() {
+                Ok(keep) => Some(keep),
+                Err(_) => return Err(format!("Invalid history.keep_runs_per_plan, it must be a number: {}", value)),
+            },
+            None => None,
+        };
+
+        let retention = match config.get("history.archive_retention_days") {
+            Some(value) => match value.parse::<u64>() {
+                Ok(days) if days > 0 => Some(Duration::from_secs(days * 24 * 60 * 60)),
+                _ => return Err(format!("Invalid history.archive_retention_days, it must be a positive number: {}", value)),
+            },
+            None => None,
+        };
+
+        let interval = match config.get("history.archive_interval") {
+            Some(value) => match parse_duration(value) {
+                Ok(interval) if !interval.is_zero() => interval,
+                Ok(_) => return Err(String::from("Invalid history.archive_interval, it cannot be zero")),
+                Err(e) => return Err(format!("Invalid history.archive_interval: {}", e)),
+            },
+            None => DEFAULT_INTERVAL,
+        };
+
+        if archive_after.is_none() && keep_runs.is_none() && retention.is_none() {
+            return Ok(None);
+        }
+
+        return Ok(Some(Policy {
+            archive_after: archive_after,
+            keep_runs: keep_runs,
+            retention: retention,
+            interval: interval,
+        }));
+    }
+
+    /// Archive finished runs and delete old archives, according to the policy
+    ///
+    /// Runs which are still running are never archived.
+    pub fn apply(&self) {
+        let now = Local::now();
+
+        let mut finished: Vec<(HistoryKey, DateTime<Local>)> = {
+            let history = HISTORY.read().unwrap();
+            let history = match &*history {
+                Some(history) => history,
+                None => return,
+            };
+
+            history.iter()
+                .filter(|(_, run)| run.status.is_some())
+                .map(|(key, run)| (key.clone(), run.end_time.unwrap_or(run.start_time)))
+                .collect()
+        };
+
+        // Newest runs are at the beginning, so the ones over the limit are at the end of every plan
+        finished.sort_by(|a, b| b.0.id.cmp(&a.0.id));
+
+        let mut per_plan: HashMap<(String, String), usize> = HashMap::new();
+        let mut expired: Vec<HistoryKey> = Vec::new();
+
+        for (key, end_time) in finished {
+            let count = per_plan.entry((key.set.clone(), key.plan.clone())).or_insert(0);
+            *count += 1;
+
+            let over_limit = self.keep_runs.map_or(false, |keep| *count > keep);
+            let too_old = self.archive_after.map_or(false, |after| {
+                chrono::Duration::from_std(after).map_or(false, |after| end_time + after <= now)
+            });
+
+            if over_limit || too_old {
+                expired.push(key);
+            }
+        }
+
+        for key in expired {
+            let run = {
+                let history = HISTORY.read().unwrap();
+                history.as_ref().and_then(|history| history.get(&key).cloned())
+            };
+
+            if let Some(run) = run {
+                if let Err(e) = archive_run(&key, &run) {
+                    eprintln!("Failed to archive {}: {}", key, e);
+                }
+            }
+        }
+
+        if let Some(retention) = self.retention {
+            let limit = match chrono::Duration::from_std(retention) {
+                Ok(retention) => now - retention,
+                Err(_) => return,
+            };
+
+            let archives = match list("", "", None, Some(limit)) {
+                Ok(archives) => archives,
+                Err(e) => {
+                    eprintln!("Failed to list archives: {}", e);
+                    return;
+                }
+            };
+
+            let dir = match log_dir() {
+                Ok(dir) => dir,
+                Err(_) => return,
+            };
+
+            for archive in archives {
+                println!("Deleting archive {}...", archive.file);
+                if let Err(e) = fs::remove_file(dir.join(&archive.file)) {
+                    eprintln!("Failed to delete {}: {}", archive.file, e);
+                }
+            }
+        }
+    }
+}
+
+/// Information about an archive file, parsed from its name
+/// - file => Name of the file within the log directory
+/// - archived => When the run was archived
+/// - size => Size of file in bytes
+pub struct ArchiveInfo {
+    pub file: String,
+    pub set: String,
+    pub plan: String,
+    pub id: u32,
+    pub archived: DateTime<Local>,
+    pub size: u64,
+}
+
+/// Reasons why an archive could not be read
+/// - Internal => Something is wrong on server side, e.g.: file cannot be read
+/// - InvalidName => Specified name is not a name of archive file
+/// - NotFound => Archive does not exist
+pub enum ArchiveError {
+    Internal(String),
+    InvalidName(String),
+    NotFound(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Internal(e) => write!(f, "{}", e),
+            ArchiveError::InvalidName(e) => write!(f, "{}", e),
+            ArchiveError::NotFound(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Return with the log directory, where archives are written
+pub fn log_dir() -> Result<PathBuf, String> {
+    let config = GLOBAL_CONFIG.read().unwrap();
+    let config = match &*config {
+        Some(config) => config,
+        None => return Err(String::from("Config is not initialized yet")),
+    };
+
+    match config.get("plan.rule_log") {
+        Some(dir) => Ok(PathBuf::from(dir)),
+        None => Err(String::from("Log directory is not specified in config")),
+    }
+}
+
+/// Write the log of run into an archive file, then remove the run from history with its journal
+pub fn archive_run(key: &HistoryKey, run: &RunRecord) -> Result<PathBuf, String> {
+    let time = match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        Ok(n) => n.as_secs(),
+        Err(_) => return Err(String::from("Failed to fetch time")),
+    };
+
+    println!("Archiving {}_{} output...", key, time);
+
+    let mut content = String::new();
+    for line in run.lines() {
+        content.push_str(&line);
+        content.push('\n');
+    }
+
+    let path = match gzip_enabled() {
+        true => {
+            let path = log_dir()?.join(format!("{}{}", archive_name(key, time), GZIP_EXT));
+            let file = fs::File::create(&path).map_err(|e| format!("Failed to create log file {}: {}", path.display(), e))?;
+
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            let written = encoder.write_all(content.as_bytes()).and_then(|_| encoder.finish().map(|_| ()));
+            if let Err(e) = written {
+                return Err(format!("Failed to write onto file {}: {}", path.display(), e));
+            }
+            path
+        },
+        false => {
+            let path = log_dir()?.join(archive_name(key, time));
+            if let Err(e) = fs::write(&path, content) {
+                return Err(format!("Failed to write onto file {}: {}", path.display(), e));
+            }
+            path
+        },
+    };
+
+    {
+        let mut history = HISTORY.write().unwrap();
+        if let Some(history) = &mut *history {
+            history.remove(key);
+        }
+    }
+    remove_run(key.id);
+
+    return Ok(path);
+}
+
+/// List archives, which belong to the plan set and plan and they are archived between from and to
+///
+/// Empty set or plan matches everything. Archives are ordered by their archive time.
+pub fn list(set: &str, plan: &str, from: Option<DateTime<Local>>, to: Option<DateTime<Local>>) -> Result<Vec<ArchiveInfo>, String> {
+    let dir = log_dir()?;
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+
+    let mut archives: Vec<ArchiveInfo> = Vec::new();
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let mut info = match parse_name(&path) {
+            Some(info) => info,
+            None => continue,
+        };
+
+        if !set.is_empty() && info.set != set {
+            continue;
+        }
+        if !plan.is_empty() && info.plan != plan {
+            continue;
+        }
+        if from.map_or(false, |from| info.archived < from) || to.map_or(false, |to| info.archived > to) {
+            continue;
+        }
+
+        info.size = entry.metadata().map(|m| m.len()).unwrap_or(0);
+        archives.push(info);
+    }
+
+    archives.sort_by(|a, b| a.archived.cmp(&b.archived).then(a.id.cmp(&b.id)));
+
+    return Ok(archives);
+}
+
+/// Read the content of an archive
+///
+/// Only archive files directly within the log directory can be read, so the name cannot contain path.
+pub fn read(file: &str) -> Result<String, ArchiveError> {
+    let path = Path::new(file);
+    if file.contains('/') || file.contains('\\') || path.file_name().map_or(true, |name| name != file) {
+        return Err(ArchiveError::InvalidName(format!("Invalid archive name: {}", file)));
+    }
+    if parse_name(path).is_none() {
+        return Err(ArchiveError::InvalidName(format!("Not an archive: {}", file)));
+    }
+
+    let path = log_dir().map_err(ArchiveError::Internal)?.join(file);
+
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(ArchiveError::NotFound(format!("Archive is not found: {}", file))),
+        Err(e) => return Err(ArchiveError::Internal(format!("Failed to read {}: {}", path.display(), e))),
+    };
+
+    if !file.ends_with(GZIP_EXT) {
+        return Ok(String::from_utf8_lossy(&data).into_owned());
+    }
+
+    let mut content = String::new();
+    match GzDecoder::new(&data[..]).read_to_string(&mut content) {
+        Ok(_) => Ok(content),
+        Err(e) => Err(ArchiveError::Internal(format!("Failed to decompress {}: {}", path.display(), e))),
+    }
+}
+
+/// Parse the date filter of archive list: either RFC 3339 time or `YYYY-MM-DD` date
+///
+/// At date, the beginning of the day is taken, or the end of the day if `end_of_day` is true.
+pub fn parse_bound(text: &str, end_of_day: bool) -> Result<Option<DateTime<Local>>, String> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(time) = parse_time(text) {
+        return Ok(Some(time));
+    }
+
+    let date = match NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        Ok(date) => date,
+        Err(_) => return Err(format!("Invalid date, it must be YYYY-MM-DD or RFC 3339 time: {}", text)),
+    };
+
+    let time = match end_of_day {
+        true => date.and_hms_milli_opt(23, 59, 59, 999),
+        false => date.and_hms_opt(0, 0, 0),
+    };
+
+    match time.and_then(|time| Local.from_local_datetime(&time).earliest()) {
+        Some(time) => Ok(Some(time)),
+        None => Err(format!("Invalid date: {}", text)),
+    }
+}
+
+/// Check that archives have to be compressed
+fn gzip_enabled() -> bool {
+    let config = GLOBAL_CONFIG.read().unwrap();
+    match &*config {
+        Some(config) => config.get("history.archive_gzip").map_or(false, |value| value == "yes"),
+        None => false,
+    }
+}
+
+/// Name of archive file: `<set>.<plan>(<id>)_<epoch>.log`
+fn archive_name(key: &HistoryKey, time: u64) -> String {
+    return format!("{}.{}({})_{}{}", escape_name(&key.set), escape_name(&key.plan), key.id, time, ARCHIVE_EXT);
+}
+
+/// Escape the dots of set or plan name, because dot separates them in the archive name
+fn escape_name(name: &str) -> String {
+    return name.replace('%', "%25").replace('.', "%2E");
+}
+
+/// Restore the escaped set or plan name
+fn unescape_name(name: &str) -> String {
+    return name.replace("%2E", ".").replace("%25", "%");
+}
+
+/// Parse name of archive file: `<set>.<plan>(<id>)_<epoch>.log`, optionally with `.gz` extension
+///
+/// Set and plan names cannot contain unescaped dot, so name is split at the first dot.
+fn parse_name(path: &Path) -> Option<ArchiveInfo> {
+    let file = path.file_name()?.to_str()?;
+    let name = file.strip_suffix(GZIP_EXT).unwrap_or(file);
+    let name = name.strip_suffix(ARCHIVE_EXT)?;
+
+    let (name, epoch) = name.rsplit_once('_')?;
+    let epoch = epoch.parse::<i64>().ok()?;
+
+    let name = name.strip_suffix(')')?;
+    let (name, id) = name.rsplit_once('(')?;
+    let id = id.parse::<u32>().ok()?;
+
+    let (set, plan) = name.split_once('.')?;
+
+    return Some(ArchiveInfo {
+        file: String::from(file),
+        set: unescape_name(set),
+        plan: unescape_name(plan),
+        id: id,
+        archived: Local.timestamp_opt(epoch, 0).single()?,
+        size: 0,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(set: &str, plan: &str, id: u32) -> HistoryKey {
+        return HistoryKey { id: id, set: String::from(set), plan: String::from(plan), uuid: String::new() };
+    }
+
+    #[test]
+    fn parse_archive_name() {
+        let info = parse_name(Path::new("daily%2Ejobs.backup(12)_1700000000.log.gz")).unwrap();
+        assert_eq!(info.set, "daily.jobs");
+        assert_eq!(info.plan, "backup");
+        assert_eq!(info.id, 12);
+        assert_eq!(info.archived.timestamp(), 1700000000);
+
+        assert!(parse_name(Path::new("set.plan(1)_1700000000.log")).is_some());
+        assert!(parse_name(Path::new("plan(1)_1700000000.log")).is_none());
+        assert!(parse_name(Path::new("set.plan(x)_1700000000.log")).is_none());
+        assert!(parse_name(Path::new("set.plan(1)_1700000000.txt")).is_none());
+    }
+
+    #[test]
+    fn dotted_names_round_trip() {
+        for (set, plan) in [("test#2", "test.something.else"), ("daily.jobs", "backup"), ("a.b", "c.d"), ("100%", "%2E.%25")] {
+            let name = archive_name(&key(set, plan, 7), 1700000000);
+            assert_eq!(name.matches('.').count(), 2, "{}", name);
+
+            let info = parse_name(Path::new(&name)).unwrap();
+            assert_eq!((&info.set[..], &info.plan[..], info.id), (set, plan, 7));
+        }
+
+        assert_eq!(archive_name(&key("test#2", "test.something.else", 3), 1700000000), "test#2.test%2Esomething%2Eelse(3)_1700000000.log");
+    }
+}
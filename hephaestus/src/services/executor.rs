@@ -0,0 +1,735 @@
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use chrono::Local;
+
+use hephaestus_plan::structs::plan::Plan;
+use hephaestus_plan::structs::step::Step;
+use hephaestus_plan::structs::enums::{JoinMode, StepOutputType, StepStatus, StepType};
+
+use crate::structs::run::{LogEntry, StepRecord};
+
+use crate::GLOBAL_CONFIG;
+use crate::STEP_SLOTS;
+
+use super::approval;
+use super::history::{write_entry, write_message, write_step};
+
+/// Possible states of a step during scheduling
+enum Readiness {
+    Ready,
+    Wait,
+    Skip,
+}
+
+/// Decision of scheduler about a pending step:
+/// - Start => Step can be started now
+/// - Wait => Step has to wait for its parents or for a free place
+/// - Skip => Step never runs, because its parents have not ended properly
+/// - Cancel => Step never runs, because plan has been cancelled
+/// - Expire => Step never runs, because plan has timed out
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Start,
+    Wait,
+    Skip,
+    Cancel,
+    Expire,
+}
+
+/// Counter of running steps, which is shared by every plan, to apply the global step limit
+pub struct StepSlots {
+    running: Mutex<usize>,
+    released: Condvar,
+}
+
+impl StepSlots {
+    pub const fn new() -> StepSlots {
+        return StepSlots {
+            running: Mutex::new(0),
+            released: Condvar::new(),
+        };
+    }
+
+    /// Wait until the number of running steps goes under the limit, then allocate a slot
+    fn acquire(&self, limit: Option<usize>) -> StepSlot<'_> {
+        let mut running = self.running.lock().unwrap();
+
+        if let Some(limit) = limit {
+            while *running >= limit {
+                running = self.released.wait(running).unwrap();
+            }
+        }
+
+        *running += 1;
+        return StepSlot { slots: self };
+    }
+}
+
+/// Slot of the global step limit, it is released when it is dropped
+struct StepSlot<'a> {
+    slots: &'a StepSlots,
+}
+
+impl Drop for StepSlot<'_> {
+    fn drop(&mut self) {
+        let mut running = self.slots.running.lock().unwrap();
+        *running -= 1;
+        self.slots.released.notify_all();
+    }
+}
+
+/// Execute steps of a plan
+///
+/// Plan steps form a graph by their parents, they are considered in topological order. Every step, whose parents
+/// have completed with the proper status, is started immediately on its own thread. Number of parallel steps are limited by the plan's `max_parallel`
+/// attribute and by the `plan.max_parallel_steps` config setting, which is applied for all plans together.
+/// If plan has timeout, running steps are killed when it expires and no more step is started.
+/// Same happens when cancel flag is set, but then remaining steps are marked as cancelled.
+/// Finally steps are exceptions, they are run in both cases and they are limited only by their own timeout.
+/// Approval steps wait for a decision, they count into `max_parallel` but not into the global limit.
+/// Overall status of plan is the status of the first failed step in topological order.
+pub fn run_plan(id: u32, plan: &mut Plan, cancel: Arc<AtomicBool>) {
+    let plan_limit = match plan.max_parallel {
+        Some(limit) if limit > 0 => limit,
+        _ => usize::MAX,
+    };
+    let global_limit = global_step_limit();
+    let deadline = plan.timeout.map(|timeout| Instant::now() + timeout);
+
+    // Steps are considered in topological order, so they are started and listed in the order of dependencies
+    let order = plan.topological_order();
+
+    // Every step is recorded as waiting, so status shows the whole plan from the beginning
+    for index in &order {
+        write_step(id, StepRecord::new(&plan.steps[*index]));
+    }
+
+    let mut completion_list: HashMap<String, StepStatus> = HashMap::new();
+    let mut running: usize = 0;
+    let mut plan_timed_out = false;
+
+    let (tx, rx) = mpsc::channel::<(usize, Step)>();
+
+    plan.status = StepStatus::Ok;
+
+    // At rerun, kept steps are taken as completed with their original status
+    let (kept, mut pending) = split_kept(plan, &order);
+    for (index, status) in kept {
+        let step = &mut plan.steps[index];
+        step.status = status.clone();
+        completion_list.insert(step.step_name.clone(), status.clone());
+        write_message(id, format!("----> {} => {:?}, kept from the original run", step.step_name, step.status), StepOutputType::Info);
+        record_skipped(id, step, status);
+    }
+
+    loop {
+        /*---------------------------------------------------------------------------------------*/
+        /* Start every step which can be started and drop that ones, which never be run         */
+        /*---------------------------------------------------------------------------------------*/
+        let mut waiting: Vec<usize> = Vec::new();
+
+        let expired = expired_at(deadline);
+        let cancelled = cancel.load(Ordering::SeqCst);
+
+        let pending_count = pending.len();
+
+        let decisions = schedule(&plan.steps, &pending, &mut completion_list, plan_limit.saturating_sub(running), cancelled, expired);
+
+        for (index, decision) in decisions {
+            match decision {
+                Decision::Start => {
+                    let step = &plan.steps[index];
+                    write_message(id, format!("----> {} => Pending", step.step_name), StepOutputType::Info);
+
+                    let mut step = step.clone();
+                    let tx = tx.clone();
+
+                    // Finally steps are stopped only by their own timeout
+                    let (deadline, cancel) = match step.step_type == StepType::Finally {
+                        true => (None, Arc::new(AtomicBool::new(false))),
+                        false => (deadline, cancel.clone()),
+                    };
+
+                    std::thread::spawn(move || {
+                        {
+                            // Approval step does not run anything, so it does not need a slot of the global limit
+                            let _slot = match step.step_type {
+                                StepType::Approval => None,
+                                _ => Some(STEP_SLOTS.acquire(global_limit)),
+                            };
+                            let name = step.step_name.clone();
+
+                            let mut record = StepRecord::new(&step);
+                            record.start_time = Some(Local::now());
+                            write_step(id, record.clone());
+
+                            // If step panics, it is failed, otherwise its result would never arrive and the plan would hang
+                            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                                if step.step_type == StepType::Approval {
+                                    approval::wait(id, &mut step, deadline, &cancel);
+                                }
+                                else {
+                                    // Output of step is written into history as soon as it is read
+                                    step.execute(deadline, &cancel, |line| {
+                                        write_entry(id, LogEntry {
+                                            timestamp: line.time,
+                                            stream: line.out_type,
+                                            step: name.clone(),
+                                            text: line.text,
+                                        });
+                                    });
+                                }
+                            }));
+
+                            if outcome.is_err() {
+                                step.status = StepStatus::Failed;
+                                write_entry(id, LogEntry::new(&name, StepOutputType::Error, String::from("----> Step has failed unexpectedly, its thread has panicked")));
+                            }
+
+                            record.status = Some(step.status.clone());
+                            record.exit_code = step.exit_code;
+                            record.attempt = step.attempt;
+                            record.end_time = Some(Local::now());
+                            write_step(id, record);
+                        }
+                        let _ = tx.send((index, step));
+                    });
+
+                    running += 1;
+                },
+                Decision::Wait => waiting.push(index),
+                Decision::Skip => {
+                    let step = &plan.steps[index];
+                    write_message(id, format!("----> {} => {:?}", step.step_name, step.status), StepOutputType::Info);
+                    record_skipped(id, step, StepStatus::NotRun);
+                },
+                Decision::Cancel => {
+                    let step = &mut plan.steps[index];
+                    step.status = StepStatus::Cancelled;
+                    write_message(id, format!("----> {} => {:?}", step.step_name, step.status), StepOutputType::Error);
+                    record_skipped(id, step, StepStatus::Cancelled);
+                },
+                Decision::Expire => {
+                    let step = &plan.steps[index];
+                    plan_timed_out = true;
+                    write_message(id, format!("----> {} => {:?}, plan has timed out", step.step_name, step.status), StepOutputType::Error);
+                    record_skipped(id, step, StepStatus::NotRun);
+                },
+            }
+        }
+
+        // Decisions of this round can make other steps ready (e.g.: finally steps), so check them again
+        let progressed = waiting.len() < pending_count;
+        pending = waiting;
+
+        if running == 0 {
+            if progressed && !pending.is_empty() {
+                continue;
+            }
+            break;
+        }
+
+        /*---------------------------------------------------------------------------------------*/
+        /* Wait until a step is completed, then record its status                                */
+        /*---------------------------------------------------------------------------------------*/
+        let (index, step) = match rx.recv() {
+            Ok(result) => result,
+            Err(_) => break,
+        };
+        running -= 1;
+
+        write_message(id, format!("----> {} => {:?}", step.step_name, step.status), StepOutputType::Info);
+
+        completion_list.insert(step.step_name.clone(), step.status.clone());
+        plan.steps[index] = step;
+    }
+
+    plan.status = overall_status(order.iter().map(|index| &plan.steps[*index].status), plan_timed_out, cancel.load(Ordering::SeqCst));
+
+    // Parser does not allow undefined parent, but if something still remained, it is not run for sure
+    for index in pending {
+        let step = &plan.steps[index];
+        write_message(id, format!("----> {} => {:?}", step.step_name, step.status), StepOutputType::Info);
+    }
+}
+
+/// Step as it would be executed in a dry run
+/// - wave => Steps of the same wave can run parallel, every wave starts after the previous one has ended.
+///   It is None if step would not run.
+/// - status => Expected status of step, it is OK if it is not assumed otherwise
+/// - assumed => Status is specified by the what-if assumptions
+pub struct PlannedStep {
+    pub step: Step,
+    pub wave: Option<u32>,
+    pub status: StepStatus,
+    pub assumed: bool,
+}
+
+/// Walk through the plan with the same dependency logic like `run_plan`, but without running anything
+///
+/// Every started step is expected to end with OK, unless other status is assumed for it. Plan limits,
+/// timeouts and approvals are not taken into account. Expected overall status of plan is returned too.
+pub fn simulate(plan: &Plan, assume: &HashMap<String, StepStatus>) -> (Vec<PlannedStep>, StepStatus) {
+    let mut planned: Vec<PlannedStep> = Vec::new();
+    let mut completion_list: HashMap<String, StepStatus> = HashMap::new();
+    let order = plan.topological_order();
+    let mut pending: Vec<usize> = order.clone();
+    let mut wave: u32 = 0;
+
+    loop {
+        let mut ready: Vec<usize> = Vec::new();
+        let mut waiting: Vec<usize> = Vec::new();
+        let pending_count = pending.len();
+
+        for (index, decision) in schedule(&plan.steps, &pending, &mut completion_list, usize::MAX, false, false) {
+            match decision {
+                Decision::Start => ready.push(index),
+                Decision::Wait => waiting.push(index),
+                _ => planned.push(PlannedStep { step: plan.steps[index].clone(), wave: None, status: StepStatus::NotRun, assumed: false }),
+            }
+        }
+
+        let progressed = waiting.len() < pending_count;
+        pending = waiting;
+
+        if ready.is_empty() {
+            if progressed && !pending.is_empty() {
+                continue;
+            }
+            break;
+        }
+
+        // Steps which are ready together end together, so their statuses are decided at the same time
+        wave += 1;
+        for index in ready {
+            let step = &plan.steps[index];
+            let (status, assumed) = match assume.get(&step.step_name) {
+                Some(status) => (status.clone(), true),
+                None => (StepStatus::Ok, false),
+            };
+
+            completion_list.insert(step.step_name.clone(), status.clone());
+            planned.push(PlannedStep { step: step.clone(), wave: Some(wave), status: status, assumed: assumed });
+        }
+    }
+
+    for index in pending {
+        planned.push(PlannedStep { step: plan.steps[index].clone(), wave: None, status: StepStatus::NotRun, assumed: false });
+    }
+
+    let overall = overall_status(order.iter()
+        .filter_map(|index| planned.iter().find(|p| p.step.step_name == plan.steps[*index].step_name))
+        .map(|p| &p.status), false, false);
+
+    return (planned, overall);
+}
+
+/// Split the steps, which are listed in topological order, into kept steps with their original status
+/// and into steps which have to be run
+fn split_kept(plan: &Plan, order: &[usize]) -> (Vec<(usize, StepStatus)>, Vec<usize>) {
+    let mut kept: Vec<(usize, StepStatus)> = Vec::new();
+    let mut pending: Vec<usize> = Vec::new();
+
+    for index in order.iter().cloned() {
+        match plan.kept_steps.get(&plan.steps[index].step_name) {
+            Some(status) => kept.push((index, status.clone())),
+            None => pending.push(index),
+        }
+    }
+
+    return (kept, pending);
+}
+
+/// Decide about every pending step, which are listed in topological order, in one round of scheduling
+///
+/// At most `free` steps are started. Steps which never run are put into the completion list immediately,
+/// so the steps which depend on them are decided in the same round. Except finally steps, no step is started
+/// when plan is cancelled or timed out.
+fn schedule(steps: &[Step], pending: &[usize], completion_list: &mut HashMap<String, StepStatus>, free: usize, cancelled: bool, expired: bool) -> Vec<(usize, Decision)> {
+    let mut decisions: Vec<(usize, Decision)> = Vec::new();
+    let mut started: usize = 0;
+
+    for index in pending.iter().cloned() {
+        let step = &steps[index];
+
+        // Finally steps are run even if plan is cancelled or timed out
+        let is_finally = step.step_type == StepType::Finally;
+
+        let decision = if cancelled && !is_finally {
+            completion_list.insert(step.step_name.clone(), StepStatus::Cancelled);
+            Decision::Cancel
+        }
+        else if expired && !is_finally {
+            completion_list.insert(step.step_name.clone(), StepStatus::NotRun);
+            Decision::Expire
+        }
+        else {
+            match check_readiness(step, steps, completion_list) {
+                Readiness::Ready if started < free => {
+                    started += 1;
+                    Decision::Start
+                },
+                Readiness::Ready | Readiness::Wait => Decision::Wait,
+                Readiness::Skip => {
+                    completion_list.insert(step.step_name.clone(), StepStatus::NotRun);
+                    Decision::Skip
+                },
+            }
+        };
+
+        decisions.push((index, decision));
+    }
+
+    return decisions;
+}
+
+/// Overall status of plan from the statuses of its steps, which are listed in topological order
+///
+/// It is the status of the first step which has not ended with OK (and was not skipped), so it does not depend
+/// on which failed step has finished last. If plan has been cancelled or timed out, it is the status of plan.
+fn overall_status<'a, I>(statuses: I, timed_out: bool, cancelled: bool) -> StepStatus
+where I: Iterator<Item = &'a StepStatus> {
+    if cancelled {
+        return StepStatus::Cancelled;
+    }
+
+    if timed_out {
+        return StepStatus::TimedOut;
+    }
+
+    for status in statuses {
+        if *status != StepStatus::Ok && *status != StepStatus::NotRun {
+            return status.clone();
+        }
+    }
+
+    return StepStatus::Ok;
+}
+
+/// Record a step which is ended without running
+fn record_skipped(id: u32, step: &Step, status: StepStatus) {
+    let mut record = StepRecord::new(step);
+    record.status = Some(status);
+    write_step(id, record);
+}
+
+/// Decide that step can be started, it has to wait for its parents or it will never run
+///
+/// Action steps are evaluated by their join mode. Recovery step is started as soon as any of its parents
+/// has failed, and it is skipped when all parents are ended without failure. Finally step is started when
+/// all of its parents are ended, or if it has no parent, when every non-finally step of the plan is ended.
+fn check_readiness(step: &Step, steps: &[Step], completion_list: &HashMap<String, StepStatus>) -> Readiness {
+    if step.step_type == StepType::Finally && step.parents.is_empty() {
+        let others_done = steps.iter()
+            .filter(|s| s.step_type != StepType::Finally)
+            .all(|s| completion_list.contains_key(&s.step_name));
+
+        return if others_done { Readiness::Ready } else { Readiness::Wait };
+    }
+
+    if step.parents.is_empty() {
+        return Readiness::Ready;
+    }
+
+    let statuses: Vec<Option<&StepStatus>> = step.parents.iter()
+        .map(|parent| completion_list.get(parent))
+        .collect();
+    let all_done = statuses.iter().all(|s| s.is_some());
+
+    if step.step_type == StepType::Finally {
+        return if all_done { Readiness::Ready } else { Readiness::Wait };
+    }
+
+    if step.step_type == StepType::Recovery {
+        let failed = |s: &&StepStatus| **s == StepStatus::Failed || **s == StepStatus::Nok || **s == StepStatus::TimedOut;
+
+        if statuses.iter().flatten().any(failed) {
+            return Readiness::Ready;
+        }
+        return if all_done { Readiness::Skip } else { Readiness::Wait };
+    }
+
+    let ok = |s: &&StepStatus| **s == StepStatus::Ok;
+
+    match step.join {
+        JoinMode::AllOk => {
+            if !statuses.iter().flatten().all(ok) {
+                return Readiness::Skip;
+            }
+            return if all_done { Readiness::Ready } else { Readiness::Wait };
+        },
+        JoinMode::AnyOk => {
+            if statuses.iter().flatten().any(ok) {
+                return Readiness::Ready;
+            }
+            return if all_done { Readiness::Skip } else { Readiness::Wait };
+        },
+        JoinMode::AllDone => {
+            return if all_done { Readiness::Ready } else { Readiness::Wait };
+        },
+    }
+}
+
+/// Check that deadline is already expired, if there is no deadline, it never expires
+fn expired_at(deadline: Option<Instant>) -> bool {
+    match deadline {
+        Some(deadline) => deadline <= Instant::now(),
+        None => false,
+    }
+}
+
+/// Read the global step limit from config, if it is not specified then there is no limit
+fn global_step_limit() -> Option<usize> {
+    let config = GLOBAL_CONFIG.read().unwrap();
+    let config = match &*config {
+        Some(config) => config,
+        None => return None,
+    };
+
+    match config.get("plan.max_parallel_steps") {
+        Some(limit) => match limit.parse::<usize>() {
+            Ok(limit) if limit > 0 => Some(limit),
+            _ => {
+                eprintln!("Property 'plan.max_parallel_steps' is not a positive number: {}", limit);
+                None
+            }
+        },
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+    use hephaestus_plan::parser::parse_plan;
+
+    fn plan(content: &str) -> Plan {
+        return parse_plan(content, "test.conf").unwrap();
+    }
+
+    /// Schedule the plan like `run_plan`, but without running anything: started steps end in the order of their start,
+    /// with the status specified in `results` or with OK. Plan is cancelled when `cancel_after` step has ended.
+    /// Events are returned in order, e.g.: `start s1`, `end s1`, `skip s2`, and the overall status of plan.
+    fn drive(plan: &Plan, results: &[(&str, StepStatus)], cancel_after: Option<&str>) -> (Vec<String>, StepStatus) {
+        let limit = plan.max_parallel.unwrap_or(usize::MAX);
+        let order = plan.topological_order();
+        let mut events: Vec<String> = Vec::new();
+        let mut completion_list: HashMap<String, StepStatus> = HashMap::new();
+        let mut running: VecDeque<usize> = VecDeque::new();
+        let mut cancelled = false;
+
+        let (kept, mut pending) = split_kept(plan, &order);
+        for (index, status) in kept {
+            events.push(format!("keep {}", plan.steps[index].step_name));
+            completion_list.insert(plan.steps[index].step_name.clone(), status);
+        }
+
+        loop {
+            let pending_count = pending.len();
+            let mut waiting: Vec<usize> = Vec::new();
+
+            for (index, decision) in schedule(&plan.steps, &pending, &mut completion_list, limit - running.len(), cancelled, false) {
+                match decision {
+                    Decision::Start => running.push_back(index),
+                    Decision::Wait => {
+                        waiting.push(index);
+                        continue;
+                    },
+                    _ => (),
+                }
+                events.push(format!("{:?} {}", decision, plan.steps[index].step_name).to_lowercase());
+            }
+
+            let progressed = waiting.len() < pending_count;
+            pending = waiting;
+
+            let name = match running.pop_front() {
+                Some(index) => &plan.steps[index].step_name,
+                None if progressed && !pending.is_empty() => continue,
+                None => break,
+            };
+
+            let status = results.iter()
+                .find(|(step, _)| step == name)
+                .map_or(StepStatus::Ok, |(_, status)| status.clone());
+            events.push(format!("end {}", name));
+            completion_list.insert(name.clone(), status);
+
+            if cancel_after == Some(&name[..]) {
+                cancelled = true;
+            }
+        }
+
+        let status = overall_status(order.iter()
+            .map(|index| completion_list.get(&plan.steps[*index].step_name).unwrap_or(&StepStatus::NotRun)), false, cancelled);
+
+        return (events, status);
+    }
+
+    #[test]
+    fn parallel_roots() {
+        let plan = plan(r#"<plan id="test"/>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d">echo</step>
+<step name="c" desc="d" parent="a">echo</step>
+"#);
+
+        assert_eq!(drive(&plan, &[], None), (
+            vec!["start a", "start b", "end a", "start c", "end b", "end c"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::Ok,
+        ));
+    }
+
+    #[test]
+    fn plan_limit() {
+        let plan = plan(r#"<plan id="test" max_parallel="2"/>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d">echo</step>
+<step name="c" desc="d">echo</step>
+"#);
+
+        assert_eq!(drive(&plan, &[], None).0, vec!["start a", "start b", "end a", "start c", "end b", "end c"]);
+    }
+
+    #[test]
+    fn global_limit() {
+        let slots = StepSlots::new();
+        let started = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            let first = slots.acquire(Some(2));
+            let _second = slots.acquire(Some(2));
+
+            scope.spawn(|| {
+                let _third = slots.acquire(Some(2));
+                started.fetch_add(1, Ordering::SeqCst);
+            });
+
+            // Third slot is allocated only when one of the others has been released
+            std::thread::sleep(Duration::from_millis(200));
+            assert_eq!(started.load(Ordering::SeqCst), 0);
+
+            drop(first);
+        });
+
+        // Every slot is released when it is dropped
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+        assert_eq!(*slots.running.lock().unwrap(), 0);
+
+        // Without limit, slot is allocated immediately
+        let _first = slots.acquire(None);
+        let _second = slots.acquire(None);
+        assert_eq!(*slots.running.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn recovery_on_failure() {
+        let plan = plan(r#"<plan id="test"/>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d" parent="a">echo</step>
+<recovery name="r" desc="d" parent="a">echo</recovery>
+"#);
+
+        assert_eq!(drive(&plan, &[], None), (
+            vec!["start a", "end a", "start b", "skip r", "end b"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::Ok,
+        ));
+        assert_eq!(drive(&plan, &[("a", StepStatus::Nok)], None), (
+            vec!["start a", "end a", "skip b", "start r", "end r"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::Nok,
+        ));
+        assert_eq!(drive(&plan, &[("a", StepStatus::TimedOut)], None), (
+            vec!["start a", "end a", "skip b", "start r", "end r"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::TimedOut,
+        ));
+    }
+
+    #[test]
+    fn join_modes() {
+        let content = r#"<plan id="test"/>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d">echo</step>
+<step name="c" desc="d" parent="a,b" join="JOIN">echo</step>
+"#;
+        let join = |mode: &str| plan(&content.replace("JOIN", mode));
+        let results = [("a", StepStatus::Nok)];
+
+        // Step is skipped as soon as one parent has failed, it does not wait for the others
+        assert_eq!(drive(&join("all_ok"), &results, None).0, vec!["start a", "start b", "end a", "skip c", "end b"]);
+        assert_eq!(drive(&join("any_ok"), &results, None).0, vec!["start a", "start b", "end a", "end b", "start c", "end c"]);
+        assert_eq!(drive(&join("any_ok"), &[("a", StepStatus::Nok), ("b", StepStatus::Failed)], None).0, vec!["start a", "start b", "end a", "end b", "skip c"]);
+        assert_eq!(drive(&join("all_done"), &[("a", StepStatus::Nok), ("b", StepStatus::Failed)], None).0, vec!["start a", "start b", "end a", "end b", "start c", "end c"]);
+    }
+
+    #[test]
+    fn parentless_finally_step() {
+        let plan = plan(r#"<plan id="test"/>
+<finally name="f" desc="d">echo</finally>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d" parent="a">echo</step>
+<step name="c" desc="d">echo</step>
+"#);
+
+        // Finally step waits for every other step, even if it is the first one in the file
+        assert_eq!(drive(&plan, &[], None).0, vec!["start a", "start c", "end a", "start b", "end c", "end b", "start f", "end f"]);
+
+        // It runs when plan is cancelled too
+        assert_eq!(drive(&plan, &[], Some("a")), (
+            vec!["start a", "start c", "end a", "cancel b", "end c", "start f", "end f"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::Cancelled,
+        ));
+    }
+
+    #[test]
+    fn expired_plan() {
+        let plan = plan(r#"<plan id="test"/>
+<step name="a" desc="d">echo</step>
+<finally name="f" desc="d" parent="a">echo</finally>
+"#);
+        let mut completion_list: HashMap<String, StepStatus> = HashMap::new();
+
+        assert_eq!(schedule(&plan.steps, &[0, 1], &mut completion_list, usize::MAX, false, true), vec![(0, Decision::Expire), (1, Decision::Start)]);
+        assert_eq!(completion_list.get("a"), Some(&StepStatus::NotRun));
+    }
+
+    #[test]
+    fn kept_steps_on_rerun() {
+        let mut plan = plan(r#"<plan id="test"/>
+<step name="a" desc="d">echo</step>
+<step name="b" desc="d" parent="a">echo</step>
+<recovery name="r" desc="d" parent="a">echo</recovery>
+<step name="c" desc="d" parent="b">echo</step>
+"#);
+
+        plan.kept_steps.insert(String::from("a"), StepStatus::Ok);
+        plan.kept_steps.insert(String::from("b"), StepStatus::Ok);
+        assert_eq!(drive(&plan, &[], None).0, vec!["keep a", "keep b", "skip r", "start c", "end c"]);
+
+        plan.kept_steps.remove("b");
+        plan.kept_steps.insert(String::from("a"), StepStatus::Nok);
+        assert_eq!(drive(&plan, &[], None), (
+            vec!["keep a", "skip b", "start r", "skip c", "end r"].iter().map(|e| e.to_string()).collect(),
+            StepStatus::Nok,
+        ));
+    }
+
+    #[test]
+    fn overall_status_of_first_failed_step() {
+        let statuses = [StepStatus::Ok, StepStatus::NotRun, StepStatus::Nok, StepStatus::Failed];
+
+        // Status is decided by the order of steps, not by the order of their end
+        assert_eq!(overall_status(statuses.iter(), false, false), StepStatus::Nok);
+        assert_eq!(overall_status(statuses.iter().skip(3), false, false), StepStatus::Failed);
+        assert_eq!(overall_status(statuses.iter().take(2), false, false), StepStatus::Ok);
+        assert_eq!(overall_status(statuses.iter(), true, false), StepStatus::TimedOut);
+        assert_eq!(overall_status(statuses.iter(), true, true), StepStatus::Cancelled);
+    }
+}
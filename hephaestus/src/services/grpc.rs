@@ -1,25 +1,32 @@
 use std::fs;
-use std::io::Write;
 use std::path::Path;
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::Ordering;
 
-use tonic::transport::{Identity, ServerTlsConfig};
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
 use tonic::{transport::Server, Request, Response, Status};
+use tokio_stream::wrappers::ReceiverStream;
 
 use hephaestus::hephaestus_server::{Hephaestus, HephaestusServer};
-use hephaestus::{Empty, List, PlanSetArg, PlanArg, PlanId, Dictionary, PlanStep, PlanDetails, PlanHistory, PlanList};
+use hephaestus::{Empty, List, PlanSetArg, PlanArg, PlanId, Dictionary, PlanStep, PlanParameter, PlanDetails, PlanList, RunStatus, RunSummary, StepRecord, LogEntry, ArchiveFilter, ArchiveInfo, ArchiveList, ArchiveName, ArchiveContent, ScheduleInfo, ScheduleList, RerunArg, ApprovalArg, ApprovalDecision, DryRunArg, DryRunResult, PlannedStep, ValidationResult};
 
-use chrono::Datelike;
-use chrono::Timelike;
+use hephaestus_plan::structs::enums::StepOutputType;
+use hephaestus_plan::lint;
 
-use crate::structs::plan::Plan;
-use crate::structs::step::Step;
 use crate::structs::historey_key::HistoryKey;
-use crate::structs::enums::{StepOutputType, StepStatus, StepType};
+use crate::structs::run::{duration_ms, format_time, RunRecord};
 
 use crate::GLOBAL_CONFIG;
 use crate::HISTORY;
+use crate::RUNNING_PLANS;
+use crate::SCHEDULES;
+
+use super::approval::{self, ApprovalError};
+use super::archive::{self, ArchiveError};
+use super::runner::{self, RerunMode, StartError};
+use super::scheduler;
+use super::history::write_message;
 
 mod hephaestus {
     tonic::include_proto!("hephaestus");
@@ -71,7 +78,7 @@ impl Hephaestus for HephaestusGrpc {
         return Ok(Response::new(list));
     }
 
-    /// This gRPC endpoint list all *.conf files within specified plan set
+    /// This gRPC endpoint list all plan files (*.conf, *.toml and *.yaml) within specified plan set
     async fn list_plans(&self, request: Request<PlanSetArg>) -> Result<Response<List>, Status> {
         let arg = request.into_inner();
         let set_name = arg.name;
@@ -102,7 +109,7 @@ impl Hephaestus for HephaestusGrpc {
                                 None => return Err(Status::internal(String::from("Could not parse directory"))),
                             };
 
-                            if full_path.ends_with(".conf") {
+                            if hephaestus_plan::parser::is_plan_file(&path) {
                                 let rule_name = full_path.split(".")
                                     .collect::<Vec<&str>>();
 
@@ -116,6 +123,10 @@ impl Hephaestus for HephaestusGrpc {
             None => return Err(Status::internal(String::from("Property 'plan.rule_dir' is not specified in config"))),
         }
 
+        // Plan which is defined in more files is listed once, it is reported when it is executed
+        rules.sort();
+        rules.dedup();
+
         let list = List {
             list: rules,
         };
@@ -140,14 +151,16 @@ impl Hephaestus for HephaestusGrpc {
             None => return Err(Status::internal(String::from("Property 'plan.rule_dir' is not specified in config"))),
         };
 
-        let rule_path = format!("{}/{}/{}.conf", rule_dir, set, plan_name);
-        let rule_path = Path::new(&rule_path);
+        let rule_path = match hephaestus_plan::parser::find_plan_file(&Path::new(rule_dir).join(&set), &plan_name) {
+            Ok(path) => path,
+            Err(e) => return Err(Status::failed_precondition(e)),
+        };
 
         if !rule_path.exists() {
             return Err(Status::not_found(String::from("Specified rule does not exist")));
         }
 
-        let plan = match super::parser::collect_steps(rule_path) {
+        let plan = match hephaestus_plan::parser::collect_steps(&rule_path) {
             Ok(plan) => plan,
             Err(e) => return Err(Status::internal(format!("Failed to parse rule: {}", e))),
         };
@@ -158,8 +171,9 @@ impl Hephaestus for HephaestusGrpc {
                 desc: x.description.clone(),
                 r#type: format!("{:?}", x.step_type),
                 user: if x.user.is_some() { x.user.clone().unwrap() } else { String::new() },
-                action: format!("{}", x.action.clone().unwrap()),
-                parent: if x.parent.is_some() { x.parent.clone().unwrap() } else { String::new() },
+                action: x.action.as_ref().map_or(String::new(), |a| a.to_string()),
+                parent: x.parents.join(","),
+                join: format!("{:?}", x.join),
                 envvars: {
                     let mut vars: Vec<Dictionary> = Vec::new();
 
@@ -172,19 +186,68 @@ impl Hephaestus for HephaestusGrpc {
 
                     vars
                 },
+                timeout: match x.timeout {
+                    Some(t) => format!("{:?}", t),
+                    None => String::new(),
+                },
+                retry: x.retry,
+                retry_delay: format!("{:?}", x.retry_delay),
+                retry_backoff: x.retry_backoff,
             })
             .collect();
         
+        let params: Vec<PlanParameter> = plan.params.iter()
+            .map(|x| PlanParameter {
+                name: x.name.clone(),
+                r#type: format!("{:?}", x.param_type),
+                default: match &x.default {
+                    Some(d) => d.clone(),
+                    None => String::new(),
+                },
+                required: x.default.is_none(),
+            })
+            .collect();
+
         let plan = PlanDetails {
             id: plan_name,
             steps: steps,
+            params: params,
         };
 
         return Ok(Response::new(plan));
     }
 
+    /// This gRPC endpoint checks a plan file and returns with every problem of it, plan is valid if there is no problem
+    async fn validate_plan(&self, request: Request<PlanArg>) -> Result<Response<ValidationResult>, Status> {
+        let arg = request.into_inner();
+
+        let rule_dir = {
+            let config = GLOBAL_CONFIG.read().unwrap();
+            let config = match &*config {
+                Some(config) => config,
+                None => return Err(Status::internal(String::from("Configuration is not available"))),
+            };
+
+            match config.get("plan.rule_dir") {
+                Some(rule_dir) => rule_dir.clone(),
+                None => return Err(Status::internal(String::from("Property 'plan.rule_dir' is not specified in config"))),
+            }
+        };
+
+        let rule_path = match hephaestus_plan::parser::find_plan_file(&Path::new(&rule_dir).join(&arg.set), &arg.plan) {
+            Ok(path) => path,
+            Err(e) => return Ok(Response::new(ValidationResult { problems: vec![e] })),
+        };
+
+        if !rule_path.exists() {
+            return Err(Status::not_found(String::from("Specified rule does not exist")));
+        }
+
+        return Ok(Response::new(ValidationResult { problems: lint::lint_file(&rule_path) }));
+    }
+
     async fn show_plans(&self, _request: Request<Empty>) -> Result<Response<PlanList>, Status> {
-        let ids: Vec<PlanId> = {
+        let (ids, runs): (Vec<PlanId>, Vec<RunSummary>) = {
             let history = HISTORY.read().unwrap();
             let history = match &*history {
                 Some(h) => h,
@@ -192,317 +255,428 @@ impl Hephaestus for HephaestusGrpc {
             };
 
             let mut collected: Vec<PlanId> = Vec::new();
-
-            for (key, _) in history.iter() {
-                collected.push(PlanId {id: key.id, set: key.set.clone(), plan: key.plan.clone()});
+            let mut runs: Vec<RunSummary> = Vec::new();
+
+            for (key, run) in history.iter() {
+                let id = PlanId {id: key.id, set: key.set.clone(), plan: key.plan.clone(), uuid: key.uuid.clone()};
+                runs.push(RunSummary {
+                    id: Some(id.clone()),
+                    status: run.state(),
+                    requester: run.requester.clone(),
+                    start_time: format_time(&run.start_time),
+                    end_time: run.end_time.as_ref().map_or(String::new(), format_time),
+                    duration_ms: duration_ms(&run.start_time, &run.end_time),
+                    plan_hash: run.plan_hash.clone(),
+                });
+                collected.push(id);
             }
 
-            collected
+            (collected, runs)
         };
 
         let list = PlanList {
             ids: ids,
+            runs: runs,
         };
 
         return Ok(Response::new(list));
     }
 
     /// This gRPC endpoint is responsible to display a scheduled plan status and its log
-    async fn show_status(&self, request: Request<PlanId>) -> Result<Response<PlanHistory>, Status> {
+    async fn show_status(&self, request: Request<PlanId>) -> Result<Response<RunStatus>, Status> {
         let arg = request.into_inner();
-        let id = HistoryKey { id: arg.id, set: String::new(), plan: String::new() };
+        let id = HistoryKey { id: arg.id, set: String::new(), plan: String::new(), uuid: String::new() };
 
-        let hist: Vec<String> = {
+        let response = {
             let history = HISTORY.read().unwrap();
             let history = match &*history {
                 Some(h) => h,
                 None => return Err(Status::internal(String::from("History is not initialized yet"))),
             };
 
-            
-            let vec = match history.get(&id)  {
-                Some(v) => v,
+            match history.get_key_value(&id) {
+                Some((key, run)) => run_status(key, run, 0),
                 None => return Err(Status::not_found(String::from("Id is not found"))),
-            };
-
-            vec.clone()
-        };
-
-        let response = PlanHistory {
-            history: hist,
+            }
         };
 
         return Ok(Response::new(response));
     }
 
-    /// This gRPC endpoint is responsible to schedule a new task and start it on async way
-    async fn execute(&self, request: Request<PlanArg>) -> Result<Response<PlanId>, Status> {
+    type FollowStatusStream = ReceiverStream<Result<RunStatus, Status>>;
+
+    /// This gRPC endpoint sends the status of a scheduled plan, then keep sending the changes until plan is running
+    /// 
+    /// Every message contains the current status of plan and its steps, but only the new log entries.
+    async fn follow_status(&self, request: Request<PlanId>) -> Result<Response<Self::FollowStatusStream>, Status> {
         let arg = request.into_inner();
-        let set = arg.set;
-        let plan_name = arg.plan;
+        let id = HistoryKey { id: arg.id, set: String::new(), plan: String::new(), uuid: String::new() };
 
-        let rule_dir = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            let config = match &*config {
-                Some(config) => config,
-                None => return Err(Status::internal(String::from("Configuration is not available"))),
+        {
+            let history = HISTORY.read().unwrap();
+            let history = match &*history {
+                Some(h) => h,
+                None => return Err(Status::internal(String::from("History is not initialized yet"))),
             };
-            match config.get("plan.rule_dir") {
-                Some(dir) => dir.clone(),
-                None => return Err(Status::internal(String::from("Rule directory is not specified in config"))),
+
+            if !history.contains_key(&id) {
+                return Err(Status::not_found(String::from("Id is not found")));
             }
-        };
+        }
 
-        // First we need to figure out what is the next id and allocate a new output list in it
-        let mut plan_info: (u32, Plan, String) = {
-            let mut history = HISTORY.write().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
 
-            let history = match &mut *history {
-                Some(h) => h,
-                None => return Err(Status::internal(String::from("History is not initialized yet"))),
-            };
+        tokio::spawn(async move {
+            let mut sent: usize = 0;
+            let mut last_state = String::new();
 
-            let mut max_key: u32 = 0;
+            loop {
+                // Check running state before reading, so the last lines are surely sent after plan has ended
+                let running = {
+                    let running = RUNNING_PLANS.lock().unwrap();
+                    running.contains_key(&id.id)
+                };
 
-            for (key, _) in history.iter() {
-                if key.id > max_key {
-                    max_key = key.id;
+                let status: Option<RunStatus> = {
+                    let history = HISTORY.read().unwrap();
+                    match &*history {
+                        Some(h) => h.get_key_value(&id).map(|(key, run)| run_status(key, run, sent)),
+                        None => None,
+                    }
+                };
+
+                // History might be dumped meanwhile
+                let status = match status {
+                    Some(status) => status,
+                    None => break,
+                };
+
+                let state: Vec<&str> = status.steps.iter().map(|s| s.status.as_str()).collect();
+                let state = format!("{} {}", status.status, state.join(","));
+
+                if status.log.len() > 0 || state != last_state {
+                    sent += status.log.len();
+                    last_state = state;
+                    if let Err(_) = tx.send(Ok(status)).await {
+                        // Client has disconnected
+                        break;
+                    }
+                }
+
+                if !running {
+                    break;
                 }
+
+                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
             }
+        });
 
-            let next_id = max_key + 1;
+        return Ok(Response::new(ReceiverStream::new(rx)));
+    }
 
-            let key = HistoryKey { id: next_id, set: set.clone(), plan: plan_name.clone() };
-            history.insert(key.clone(), Vec::new());
+    /// This gRPC endpoint is responsible to schedule a new task and start it on async way
+    async fn execute(&self, request: Request<PlanArg>) -> Result<Response<PlanId>, Status> {
+        let requester = requester(&request);
+        let arg = request.into_inner();
 
-            let path = format!("{}/{}/{}.conf", rule_dir, set, plan_name);
-            let path = Path::new(&path);
+        match runner::start_plan(&arg.set, &arg.plan, &arg.params, requester) {
+            Ok(key) => return Ok(Response::new(PlanId { id: key.id, set: key.set, plan: key.plan, uuid: key.uuid })),
+            Err(StartError::Internal(e)) => return Err(Status::internal(e)),
+            Err(StartError::InvalidArgument(e)) => return Err(Status::invalid_argument(e)),
+            Err(StartError::Rejected(e)) => return Err(Status::failed_precondition(e)),
+            Err(StartError::NotFound(e)) => return Err(Status::not_found(e)),
+        }
+    }
 
-            let plan = match super::parser::collect_steps(path) {
-                Ok(plan) => {
-                    match history.get_mut(&key) {
-                        Some(log) => log.push(msg_with_time_stamp(format!("----> {}/{} => Plan has initialized", set, plan_name), StepOutputType::Info)),
-                        None => (),
-                    }
-                    plan 
-                },
-                Err(e) => { 
-                    match history.get_mut(&key) {
-                        Some(log) => log.push(msg_with_time_stamp(format!("----> {}/{} => Failed to parse the plan: {}", set, plan_name, e), StepOutputType::Error)),
-                        None => (),
-                    }
-                    return Err(Status::internal(format!("Failed to parse file: {} {}", path.display(), e)));
-                },
-            };
+    /// This gRPC endpoint walks through a plan without running anything
+    ///
+    /// Steps which would run are returned with their resolved commands, nothing is recorded in the history.
+    async fn dry_run(&self, request: Request<DryRunArg>) -> Result<Response<DryRunResult>, Status> {
+        let arg = request.into_inner();
 
-            (next_id, plan, set.clone())
+        let (steps, status) = match runner::dry_run(&arg.set, &arg.plan, &arg.params, &arg.assume) {
+            Ok(result) => result,
+            Err(StartError::Internal(e)) => return Err(Status::internal(e)),
+            Err(StartError::InvalidArgument(e)) => return Err(Status::invalid_argument(e)),
+            Err(StartError::Rejected(e)) => return Err(Status::failed_precondition(e)),
+            Err(StartError::NotFound(e)) => return Err(Status::not_found(e)),
         };
 
-        println!("Scheduling {}({})...", plan_info.1.id, plan_info.0);
-
-        // Start batch in the background
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .unwrap();
-            rt.block_on(async move {
-                let mut completion_list: HashMap<&String, Step> = HashMap::new();
-                plan_info.1.status = StepStatus::Ok;
-
-                for step in plan_info.1.steps.iter_mut() {
-                    write_history(plan_info.0, |log| {
-                        log.push(msg_with_time_stamp(format!("----> {} => Pending", step.step_name), StepOutputType::Info));
-                    });
-
-                    let mut enable = false;
-
-                    match &step.parent {
-                        Some(p) => {
-                            if let Some(v) = completion_list.get(p) {
-                                if (v.status == StepStatus::Ok && step.step_type == StepType::Action) || 
-                                ((v.status == StepStatus::Failed || v.status == StepStatus::Nok) && step.step_type == StepType::Recovery) {
-                                    enable = true;
-                                }
-                            }
-                        }
-                        None => {
-                            enable = true;
-                        }
-                    }
+        let steps = steps.iter()
+            .map(|planned| PlannedStep {
+                name: planned.step.step_name.clone(),
+                r#type: format!("{:?}", planned.step.step_type),
+                wave: planned.wave.unwrap_or(0),
+                status: format!("{:?}", planned.status),
+                assumed: planned.assumed,
+                command: planned.step.action.as_ref().map(|action| action.cmd.clone()).unwrap_or_default(),
+                cwd: planned.step.action.as_ref().and_then(|action| action.cwd.clone()).unwrap_or_default(),
+                user: planned.step.user.clone().unwrap_or_default(),
+                env: planned.step.envvars.clone(),
+                parents: planned.step.parents.clone(),
+            })
+            .collect();
 
-                    if enable {
-                        let step_log = step.execute();
-                        if step_log.len() > 0 {
-                            {
-                                write_history(plan_info.0, |log| {
-                                    let mut msgs: Vec<String> = step_log.iter()
-                                        .map(|x| format!("{} {} {}", x.time, x.out_type, x.text))
-                                        .collect();
-                                    log.append(&mut msgs);
-                                });
-                            }
-                        }
-                        completion_list.insert(&step.step_name, step.clone());
-                    }
+        return Ok(Response::new(DryRunResult { steps: steps, status: format!("{:?}", status) }));
+    }
 
-                    if step.status != StepStatus::Ok && step.status != StepStatus::NotRun {
-                        plan_info.1.status = step.status.clone();
-                    }
+    /// This gRPC endpoint runs an ended run again from a step or from its failed steps, with the plan snapshot of original run
+    async fn rerun(&self, request: Request<RerunArg>) -> Result<Response<PlanId>, Status> {
+        let requester = requester(&request);
+        let arg = request.into_inner();
 
-                    write_history(plan_info.0, |log| {
-                        log.push(msg_with_time_stamp(format!("----> {} => {:?}", step.step_name, step.status), StepOutputType::Info));
-                    });
-                }
+        let mode = match (&arg.mode[..], arg.from_step.is_empty()) {
+            ("", false) | ("from", _) => RerunMode::From,
+            ("", true) | ("failed", _) => RerunMode::Failed,
+            (mode, _) => return Err(Status::invalid_argument(format!("Rerun mode must be from or failed: {}", mode))),
+        };
 
-                write_history(plan_info.0, |log| {
-                    log.push(msg_with_time_stamp(format!("----> Plan is ended, overall status: {:?}", plan_info.1.status), StepOutputType::Info));
-                });
+        match runner::rerun_plan(arg.id, &arg.from_step, mode, requester) {
+            Ok(key) => return Ok(Response::new(PlanId { id: key.id, set: key.set, plan: key.plan, uuid: key.uuid })),
+            Err(StartError::Internal(e)) => return Err(Status::internal(e)),
+            Err(StartError::InvalidArgument(e)) => return Err(Status::invalid_argument(e)),
+            Err(StartError::Rejected(e)) => return Err(Status::failed_precondition(e)),
+            Err(StartError::NotFound(e)) => return Err(Status::not_found(e)),
+        }
+    }
 
-                // Send updates to Hermes if enabled
-                {
-                    let tx = crate::HERMES_TX.lock().unwrap();
+    /// This gRPC endpoint cancels a running plan: running steps are killed and remaining ones are not started
+    async fn cancel(&self, request: Request<PlanId>) -> Result<Response<Empty>, Status> {
+        let arg = request.into_inner();
 
-                    if let Some(tx) = &*tx {
-                        let _ = tx.send((format!("{}/{}/{}", plan_info.2, plan_info.1.id, plan_info.0), format!("{:?}", plan_info.1.status))).await;
-                    }
+        let cancel = {
+            let running = RUNNING_PLANS.lock().unwrap();
+            running.get(&arg.id).cloned()
+        };
+
+        match cancel {
+            Some(cancel) => {
+                if cancel.swap(true, Ordering::SeqCst) {
+                    return Err(Status::failed_precondition(String::from("Plan is already being cancelled")));
                 }
-            });
-        });
 
-        // Batch is running in the backgorund, give anser back
-        return Ok(Response::new(PlanId { id: plan_info.0, set: set.clone(), plan: plan_name.clone()}));
+                println!("Cancelling {}...", arg.id);
+                write_message(arg.id, String::from("----> Cancel is requested"), StepOutputType::Info);
+            },
+            None => {
+                let history = HISTORY.read().unwrap();
+                let history = match &*history {
+                    Some(h) => h,
+                    None => return Err(Status::internal(String::from("History is not initialized yet"))),
+                };
+
+                let key = HistoryKey { id: arg.id, set: String::new(), plan: String::new(), uuid: String::new() };
+                if history.contains_key(&key) {
+                    return Err(Status::failed_precondition(String::from("Plan is not running")));
+                }
+                return Err(Status::not_found(String::from("Id is not found")));
+            },
+        }
+
+        return Ok(Response::new(Empty {}));
     }
 
-    /// Write into a file a specific output
-    async fn dump_hist(&self, request: Request<PlanId>) -> Result<Response<Empty>, Status> {
-        let plan_id = request.into_inner();
-        let id = HistoryKey { id: plan_id.id, set: String::new(), plan: String::new() };
+    /// This gRPC endpoint approves or rejects a step which is waiting for approval
+    async fn approve(&self, request: Request<ApprovalArg>) -> Result<Response<Empty>, Status> {
+        let by = requester(&request);
+        let arg = request.into_inner();
 
-        let log_dir = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            let config = match &*config {
-                Some(c) => c,
-                None => return Err(Status::internal(String::from("Config is not initialized yet"))),
-            };
+        match approval::decide(arg.id, &arg.step, !arg.reject, by, arg.comment) {
+            Ok(_) => return Ok(Response::new(Empty {})),
+            Err(ApprovalError::Internal(e)) => return Err(Status::internal(e)),
+            Err(ApprovalError::NotFound(e)) => return Err(Status::not_found(e)),
+            Err(ApprovalError::NotWaiting(e)) => return Err(Status::failed_precondition(e)),
+        }
+    }
 
-            match config.get("plan.rule_log") {
-                Some(p) => p.clone(),
-                None => return Err(Status::internal(String::from("Log directory is not specified in config"))),
-            }
+    /// This gRPC endpoint lists the archived logs, filtered by plan set, plan and archive date
+    async fn list_archives(&self, request: Request<ArchiveFilter>) -> Result<Response<ArchiveList>, Status> {
+        let filter = request.into_inner();
+
+        let from = match archive::parse_bound(&filter.from, false) {
+            Ok(from) => from,
+            Err(e) => return Err(Status::invalid_argument(e)),
+        };
+        let to = match archive::parse_bound(&filter.to, true) {
+            Ok(to) => to,
+            Err(e) => return Err(Status::invalid_argument(e)),
         };
 
+        let archives = match archive::list(&filter.set, &filter.plan, from, to) {
+            Ok(archives) => archives,
+            Err(e) => return Err(Status::internal(e)),
+        };
+
+        let archives = archives.iter()
+            .map(|info| ArchiveInfo {
+                file: info.file.clone(),
+                set: info.set.clone(),
+                plan: info.plan.clone(),
+                id: info.id,
+                archived: format_time(&info.archived),
+                size: info.size,
+            })
+            .collect();
+
+        return Ok(Response::new(ArchiveList { archives: archives }));
+    }
+
+    /// This gRPC endpoint returns the content of an archived log
+    async fn get_archive(&self, request: Request<ArchiveName>) -> Result<Response<ArchiveContent>, Status> {
+        let name = request.into_inner();
+
+        match archive::read(&name.file) {
+            Ok(content) => return Ok(Response::new(ArchiveContent { file: name.file, content: content })),
+            Err(ArchiveError::Internal(e)) => return Err(Status::internal(e)),
+            Err(ArchiveError::InvalidName(e)) => return Err(Status::invalid_argument(e)),
+            Err(ArchiveError::NotFound(e)) => return Err(Status::not_found(e)),
+        }
+    }
+
+    /// This gRPC endpoint lists the scheduled plans with their next fire time
+    async fn show_schedules(&self, _request: Request<Empty>) -> Result<Response<ScheduleList>, Status> {
+        // Plan files are read again, so recent changes are shown too, it is done out of the async runtime
+        if let Err(e) = tokio::task::spawn_blocking(scheduler::reload).await {
+            return Err(Status::internal(format!("Failed to reload schedules: {}", e)));
+        }
+
+        let schedules = SCHEDULES.lock().unwrap();
+        let schedules = schedules.iter()
+            .map(|plan| ScheduleInfo {
+                set: plan.set.clone(),
+                plan: plan.plan.clone(),
+                schedule: plan.schedule.expression.clone(),
+                timezone: plan.schedule.timezone_name(),
+                overlap: format!("{:?}", plan.schedule.overlap),
+                next_run: plan.next.as_ref().map(format_time).unwrap_or_default(),
+                queued: plan.queued,
+            })
+            .collect();
+
+        return Ok(Response::new(ScheduleList { schedules: schedules }));
+    }
+
+    /// Write into a file a specific output
+    async fn dump_hist(&self, request: Request<PlanId>) -> Result<Response<Empty>, Status> {
+        let plan_id = request.into_inner();
+        let id = HistoryKey { id: plan_id.id, set: String::new(), plan: String::new(), uuid: String::new() };
+
         let hist = {
-            let mut history = HISTORY.write().unwrap();
-            let history = match &mut *history {
+            let history = HISTORY.read().unwrap();
+            let history = match &*history {
                 Some(h) => h,
                 None => return Err(Status::internal(String::from("History is not initialized yet"))),
             };
 
-            let hist = match history.get_key_value(&id) {
+            match history.get_key_value(&id) {
                 Some(hist) => (hist.0.clone(), hist.1.clone()),
                 None => return Err(Status::not_found(String::from("Specified id is not found in online history"))),
-            };
-
-            hist
-        };
-
-        let time = {
-            match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
-                Ok(n) => n.as_secs(),
-                Err(_) => return Err(Status::internal(String::from("Failed to fetch time"))),
             }
         };
 
-        println!("Archiving {}_{} output...", hist.0, time);
-
-        let path = format!("{}/{}_{}.log", log_dir, hist.0, time);
-        let path = Path::new(&path);
-        let mut log_file = match std::fs::File::create(path) {
-            Ok(f) => f,
-            Err(e) => return Err(Status::internal(format!("Failed to create log file {}: {}", path.display(), e))),
-        };
-
-        for line in hist.1 {
-            match writeln!(log_file, "{}", line) {
-                Ok(_) => (),
-                Err(e) => return Err(Status::internal(format!("Failed to write onto file {}: {}", path.display(), e)))
-            }
+        if hist.1.status.is_none() {
+            return Err(Status::failed_precondition(String::from("Run is still running, it cannot be dumped")));
         }
 
-        {
-            let mut history = HISTORY.write().unwrap();
-            let history = match &mut *history {
-                Some(hist) => hist,
-                None => return Err(Status::internal(String::from("History is not initialized yet"))),
-            };
-
-            history.remove(&id);
+        if let Err(e) = archive::archive_run(&hist.0, &hist.1) {
+            return Err(Status::internal(e));
         }
 
         return Ok(Response::new(Empty {}));
     }
 
-    /// Dump all output from the memory
+    /// Dump all output of finished runs from the memory
     async fn dump_hist_all(&self, _request: Request<Empty>) -> Result<Response<Empty>, Status> {
-        let log_dir = {
-            let config = GLOBAL_CONFIG.read().unwrap();
-            let config = match &*config {
-                Some(c) => c,
-                None => return Err(Status::internal(String::from("Config is not initialized yet"))),
+        let runs: Vec<(HistoryKey, RunRecord)> = {
+            let history = HISTORY.read().unwrap();
+            let history = match &*history {
+                Some(h) => h,
+                None => return Err(Status::internal("History is not initlaized yet")),
             };
 
-            match config.get("plan.rule_log") {
-                Some(p) => p.clone(),
-                None => return Err(Status::internal(String::from("Log directory is not specified in config"))),
-            }
-        };
-
-        let mut history = HISTORY.write().unwrap();
-        let history = match &mut *history {
-            Some(h) => h,
-            None => return Err(Status::internal("History is not initlaized yet")),
+            // Running runs are kept, they are archived only when they are ended
+            history.iter()
+                .filter(|(_, run)| run.status.is_some())
+                .map(|(key, run)| (key.clone(), run.clone()))
+                .collect()
         };
 
-        let time = {
-            match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
-                Ok(n) => n.as_secs(),
-                Err(_) => return Err(Status::internal(String::from("Failed to fetch time"))),
+        for (key, run) in runs {
+            if let Err(e) = archive::archive_run(&key, &run) {
+                eprintln!("Failed to archive {}: {}", key, e);
             }
-        };
+        }
 
-        let mut written: Vec<HistoryKey> = Vec::new();
-
-        for (key, value) in history.iter() {
-            println!("Archiving {}_{} output...", key, time);
-            let path = format!("{}/{}_{}.log", log_dir, key, time);
-            let path = Path::new(&path);
-            let mut log_file = match std::fs::File::create(path) {
-                Ok(f) => f,
-                Err(e) => {
-                    eprintln!("Failed to archive {}: {}", path.display(), e);
-                    continue;
-                }
-            };
+        return Ok(Response::new(Empty {}));
+    }
+}
 
-            for line in value {
-                if let Err(e) = writeln!(log_file, "{}", line) {
-                    eprintln!("Failed to write {}: {}", path.display(), e);
-                    continue;
-                }
+/// Identify who has sent the request: subject of client certificate if it is provided, otherwise address of peer
+fn requester<T>(request: &Request<T>) -> String {
+    if let Some(certs) = request.peer_certs() {
+        if let Some(cert) = certs.first() {
+            if let Ok((_, cert)) = x509_parser::parse_x509_certificate(cert.get_ref()) {
+                return cert.subject().to_string();
             }
-
-            written.push(key.clone());
-        }
-
-        for ok in written {
-            history.remove(&ok);
         }
+    }
 
-        return Ok(Response::new(Empty {}));
+    match request.remote_addr() {
+        Some(addr) => addr.to_string(),
+        None => String::from("unknown"),
     }
 }
 
+/// Convert a run from history to gRPC message, log entries and their text view are sent from the specified index
+fn run_status(key: &HistoryKey, run: &RunRecord, log_from: usize) -> RunStatus {
+    return RunStatus {
+        history: run.lines().into_iter().skip(log_from).collect(),
+        id: Some(PlanId { id: key.id, set: key.set.clone(), plan: key.plan.clone(), uuid: key.uuid.clone() }),
+        status: run.state(),
+        start_time: format_time(&run.start_time),
+        end_time: run.end_time.as_ref().map_or(String::new(), format_time),
+        duration_ms: duration_ms(&run.start_time, &run.end_time),
+        requester: run.requester.clone(),
+        plan_hash: run.plan_hash.clone(),
+        plan_snapshot: run.plan_snapshot.clone(),
+        rerun_of: run.rerun_of.unwrap_or(0),
+        params: run.params.iter().cloned().collect(),
+        approvals: run.approvals.iter()
+            .map(|approval| ApprovalDecision {
+                step: approval.step.clone(),
+                approved: approval.approved,
+                by: approval.by.clone(),
+                time: format_time(&approval.time),
+                comment: approval.comment.clone(),
+            })
+            .collect(),
+        steps: run.steps.iter()
+            .map(|step| StepRecord {
+                name: step.name.clone(),
+                r#type: format!("{:?}", step.step_type),
+                status: step.state(),
+                exit_code: step.exit_code.unwrap_or(-1),
+                attempt: step.attempt,
+                start_time: step.start_time.as_ref().map_or(String::new(), format_time),
+                end_time: step.end_time.as_ref().map_or(String::new(), format_time),
+                duration_ms: match &step.start_time {
+                    Some(start) => duration_ms(start, &step.end_time),
+                    None => 0,
+                },
+            })
+            .collect(),
+        log: run.log.iter()
+            .skip(log_from)
+            .map(|entry| LogEntry {
+                timestamp: format_time(&entry.timestamp),
+                stream: format!("{:?}", entry.stream),
+                step: entry.step.clone(),
+                text: entry.text.clone(),
+            })
+            .collect(),
+    };
+}
+
 /// Start gRPC server, this must be run from a tokio runtime environment
 pub async fn start_server(config: &HashMap<String, String>) -> Result<(), Box<dyn std::error::Error>> {
     match config.get("host.grpc.address") {
@@ -546,9 +720,15 @@ pub async fn start_server(config: &HashMap<String, String>) -> Result<(), Box<dy
                 };
                 let server_identity = Identity::from_pem(server_cert, server_key);
 
-                let tls = ServerTlsConfig::new()
+                let mut tls = ServerTlsConfig::new()
                     .identity(server_identity);
 
+                // If CA is specified for clients, then clients must authenticate themselves with certificate
+                if let Some(v) = config.get("host.grpc.tls.client_ca") {
+                    let client_ca = tokio::fs::read(v).await?;
+                    tls = tls.client_ca_root(Certificate::from_pem(client_ca));
+                }
+
                 println!("Start gRPC endpoint in on {} with TLS", addr);
                 Server::builder()
                     .tls_config(tls)?
@@ -571,31 +751,3 @@ pub async fn start_server(config: &HashMap<String, String>) -> Result<(), Box<dy
 
     return Ok(());
 }
-
-/// Return message format what is in present in log files
-fn msg_with_time_stamp(msg: String, out_type: StepOutputType) -> String {
-    let now = chrono::Local::now();
-    let now = format!("{}-{:02}-{:02} {:02}:{:02}:{:02}", now.year(), now.month(), now.day(), now.hour(), now.minute(), now.second());
-
-    return format!("{} {} {}", now, out_type, msg);
-}
-
-/// Put an exclusive enqueue for history, then write into it
-fn write_history<F>(index: u32, func: F) 
-where F: Fn(&mut Vec<String>) {
-    let mut history = HISTORY.write().unwrap();
-    let history = match &mut *history {
-        Some(hist) => hist,
-        None => {
-            eprintln!("Failed to write history");
-            return;
-        }
-    };
-    let key = HistoryKey { id: index, set: String::new(), plan: String::new() };
-    match history.get_mut(&key) {
-        Some(log) => {
-            func(log);
-        },
-        None => (),
-    }
-}
\ No newline at end of file
@@ -0,0 +1,590 @@
+//! On-disk history store
+//!
+//! Every run has its own append-only journal file in `<plan.rule_log>/journal/<id>.journal`.
+//! Every line is a record, fields are separated by tabs:
+//! - `run <id> <set> <plan> <uuid> <start time> <requester>` => Metadata of run, it is always the first record
+//! - `plan <sha256> <content>` => Snapshot of the plan file, which was executed
+//! - `param <name> <value>` => Parameter value which was specified at execution
+//! - `rerun <id>` => Run is a rerun of the specified run
+//! - `approval <step> <approved|rejected> <by> <time> <comment>` => Decision about an approval step
+//! - `log <time> <stream> <step> <text>` => One line of the run's log, step is empty for messages of plan
+//! - `step <name> <type> <status> <exit code> <attempt> <start time> <end time>` => Record of a step,
+//!   it is written when step is changed, the last one is valid. Missing values are empty fields.
+//! - `end <end time> <status>` => Run has ended with this overall status
+//!
+//! Backslashes, tabs and new lines are escaped within the fields, so one record is always one line.
+//! Journals are written as the log lines are produced, and they are read back when server is started.
+//! Last allocated run ID is stored in `<plan.rule_log>/journal/sequence`, so IDs are never reused.
+
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local};
+use sha2::{Digest, Sha256};
+
+use hephaestus_plan::structs::enums::{StepOutputType, StepStatus, StepType};
+
+use crate::structs::historey_key::HistoryKey;
+use crate::structs::run::{format_time, parse_time, ApprovalRecord, LogEntry, RunRecord, StepRecord};
+
+use crate::GLOBAL_CONFIG;
+use crate::HISTORY;
+use crate::JOURNALS;
+
+/// Directory name within `plan.rule_log` where the journals of runs are stored
+const JOURNAL_DIR: &str = "journal";
+
+/// File name within journal directory where the last run ID is stored
+const SEQUENCE_FILE: &str = "sequence";
+
+/// Return with the journal directory, if log directory is specified in config
+pub fn journal_dir() -> Option<PathBuf> {
+    let config = GLOBAL_CONFIG.read().unwrap();
+    let config = match &*config {
+        Some(config) => config,
+        None => return None,
+    };
+
+    return config.get("plan.rule_log").map(|dir| Path::new(dir).join(JOURNAL_DIR));
+}
+
+/// Allocate the history of a new run and create its journal
+pub fn create_run(key: &HistoryKey, requester: String) -> Result<(), String> {
+    let run = RunRecord::new(requester);
+    let record = format!(
+        "run\t{}\t{}\t{}\t{}\t{}\t{}",
+        key.id,
+        escape(&key.set),
+        escape(&key.plan),
+        escape(&key.uuid),
+        format_time(&run.start_time),
+        escape(&run.requester),
+    );
+
+    let journal = journal_writer(key.id);
+    let mut file;
+
+    {
+        let mut history = HISTORY.write().unwrap();
+        let history = match &mut *history {
+            Some(hist) => hist,
+            None => return Err(String::from("History is not initialized yet")),
+        };
+
+        history.insert(key.clone(), run);
+        file = journal.lock().unwrap();
+    }
+
+    write_records(key.id, &mut file, &[record], true);
+
+    return Ok(());
+}
+
+/// Keep a copy and the hash of the plan file content, which is executed in the run, with the name of file
+pub fn write_snapshot(id: u32, file_name: String, content: String) {
+    let hash = format!("{:x}", Sha256::digest(content.as_bytes()));
+
+    update_run(id, |run| {
+        let record = format!("plan\t{}\t{}\t{}", hash, escape(&content), escape(&file_name));
+        run.plan_hash = hash;
+        run.plan_snapshot = content;
+        run.plan_file = file_name;
+        return vec![record];
+    });
+}
+
+/// Record the parameter values, which were specified at execution
+pub fn write_params(id: u32, params: &HashMap<String, String>) {
+    let mut params: Vec<(String, String)> = params.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+    params.sort();
+
+    update_run(id, |run| {
+        let records = params.iter()
+            .map(|(name, value)| format!("param\t{}\t{}", escape(name), escape(value)))
+            .collect();
+        run.params = params;
+        return records;
+    });
+}
+
+/// Record that run is a rerun of an earlier run
+pub fn write_rerun_of(id: u32, original: u32) {
+    update_run(id, |run| {
+        run.rerun_of = Some(original);
+        return vec![format!("rerun\t{}", original)];
+    });
+}
+
+/// Record who has approved or rejected an approval step
+pub fn write_approval(id: u32, approval: ApprovalRecord) {
+    update_run(id, |run| {
+        let record = format!(
+            "approval\t{}\t{}\t{}\t{}\t{}",
+            escape(&approval.step),
+            if approval.approved { "approved" } else { "rejected" },
+            escape(&approval.by),
+            format_time(&approval.time),
+            escape(&approval.comment),
+        );
+        run.approvals.push(approval);
+        return vec![record];
+    });
+}
+
+/// Write a message of plan into the log of run
+pub fn write_message(id: u32, text: String, out_type: StepOutputType) {
+    write_entry(id, LogEntry::new("", out_type, text));
+}
+
+/// Write a log entry into the log of run
+pub fn write_entry(id: u32, entry: LogEntry) {
+    update_run(id, |run| {
+        let record = format!("log\t{}\t{}\t{}\t{}", format_time(&entry.timestamp), entry.stream, escape(&entry.step), escape(&entry.text));
+        run.log.push(entry);
+        return vec![record];
+    });
+}
+
+/// Update the record of a step within the run, or add it if it is not recorded yet
+pub fn write_step(id: u32, step: StepRecord) {
+    update_run(id, |run| {
+        let record = format!(
+            "step\t{}\t{:?}\t{}\t{}\t{}\t{}\t{}",
+            escape(&step.name),
+            step.step_type,
+            step.status.as_ref().map_or(String::new(), |s| format!("{:?}", s)),
+            step.exit_code.map_or(String::new(), |c| c.to_string()),
+            step.attempt,
+            step.start_time.as_ref().map_or(String::new(), format_time),
+            step.end_time.as_ref().map_or(String::new(), format_time),
+        );
+
+        match run.steps.iter_mut().find(|s| s.name == step.name) {
+            Some(current) => *current = step,
+            None => run.steps.push(step),
+        }
+        return vec![record];
+    });
+}
+
+/// Record the overall status of a run, when it is ended
+pub fn end_run(id: u32, status: String) {
+    update_run(id, |run| {
+        let end_time = Local::now();
+        let record = format!("end\t{}\t{}", format_time(&end_time), escape(&status));
+        run.status = Some(status);
+        run.end_time = Some(end_time);
+        return vec![record];
+    });
+
+    // Nothing is written after the end of run, so its journal can be closed
+    JOURNALS.lock().unwrap().remove(&id);
+}
+
+/// Remove the journal of a run, e.g.: after it is archived
+pub fn remove_run(id: u32) {
+    JOURNALS.lock().unwrap().remove(&id);
+
+    let dir = match journal_dir() {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let path = dir.join(format!("{}.journal", id));
+    if path.exists() {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("Failed to remove journal {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Read the last allocated run ID, it is 0 if nothing has been allocated yet
+///
+/// Only a missing file means that, if file cannot be read or it is corrupted, error is returned,
+/// because IDs of earlier runs could be reused otherwise.
+pub fn load_sequence() -> Result<u32, String> {
+    let path = match journal_dir() {
+        Some(dir) => dir.join(SEQUENCE_FILE),
+        None => return Ok(0),
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+    };
+
+    match content.trim().parse::<u32>() {
+        Ok(id) => Ok(id),
+        Err(_) => Err(format!("Invalid content in {}: {}", path.display(), content.trim())),
+    }
+}
+
+/// Store the last allocated run ID, file is replaced at once, so it is never half written
+pub fn save_sequence(id: u32) -> Result<(), String> {
+    let dir = match journal_dir() {
+        Some(dir) => dir,
+        None => return Ok(()),
+    };
+
+    let temp = dir.join(format!("{}.tmp", SEQUENCE_FILE));
+    if let Err(e) = fs::write(&temp, format!("{}\n", id)) {
+        return Err(format!("Failed to write {}: {}", temp.display(), e));
+    }
+
+    if let Err(e) = fs::rename(&temp, dir.join(SEQUENCE_FILE)) {
+        return Err(format!("Failed to replace sequence file: {}", e));
+    }
+
+    return Ok(());
+}
+
+/// Read every journal from the disk and build the history from them
+///
+/// If a run does not have end record, the server has been stopped while it was running.
+/// These runs are closed with an interrupted message, so they are not reported as running anymore.
+pub fn load() -> HashMap<HistoryKey, RunRecord> {
+    let mut history: HashMap<HistoryKey, RunRecord> = HashMap::new();
+
+    let dir = match journal_dir() {
+        Some(dir) => dir,
+        None => return history,
+    };
+
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Failed to read journal directory {}: {}", dir.display(), e);
+            return history;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != "journal") {
+            continue;
+        }
+
+        let mut content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Failed to read journal {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        // Last record is half written, if server has been stopped while it was written, it is cut
+        // so the next records are not appended to it
+        if !content.is_empty() && !content.ends_with('\n') {
+            let length = content.rfind('\n').map_or(0, |index| index + 1);
+            eprintln!("Journal {}: incomplete last record is removed: {}", path.display(), &content[length..]);
+
+            let truncated = OpenOptions::new().write(true).open(&path).and_then(|file| file.set_len(length as u64));
+            if let Err(e) = truncated {
+                eprintln!("Failed to truncate journal {}: {}", path.display(), e);
+            }
+            content.truncate(length);
+        }
+
+        let (key, mut run) = match read_journal(&content) {
+            Ok((key, run, errors)) => {
+                for e in errors {
+                    eprintln!("Journal {}: {}", path.display(), e);
+                }
+                (key, run)
+            },
+            Err(e) => {
+                eprintln!("Journal {} is skipped: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        if run.status.is_none() {
+            let entry = LogEntry::new("", StepOutputType::Error, String::from("----> Plan is interrupted, server has been stopped while it was running"));
+            let end_time = Local::now();
+            let status = String::from("Interrupted");
+
+            write_records(key.id, &mut None, &[
+                format!("log\t{}\t{}\t\t{}", format_time(&entry.timestamp), entry.stream, escape(&entry.text)),
+                format!("end\t{}\t{}", format_time(&end_time), status),
+            ], false);
+
+            run.log.push(entry);
+            run.status = Some(status);
+            run.end_time = Some(end_time);
+        }
+
+        history.insert(key, run);
+    }
+
+    return history;
+}
+
+/// Rebuild a run from the records of its journal
+///
+/// Invalid records are skipped, so the rest of the run is still available, and they are returned as errors.
+/// Only a missing or invalid run record makes the whole journal invalid.
+fn read_journal(content: &str) -> Result<(HistoryKey, RunRecord, Vec<String>), String> {
+    let mut key: Option<HistoryKey> = None;
+    let mut run = RunRecord::new(String::new());
+    let mut errors: Vec<String> = Vec::new();
+
+    for (index, record) in content.lines().enumerate() {
+        if let Err(e) = read_record(record, &mut key, &mut run) {
+            errors.push(format!("record in line {} is skipped: {}", index + 1, e));
+        }
+    }
+
+    match key {
+        Some(key) => Ok((key, run, errors)),
+        None => Err(String::from("run record is missing or invalid")),
+    }
+}
+
+/// Process one record of journal
+fn read_record(record: &str, key: &mut Option<HistoryKey>, run: &mut RunRecord) -> Result<(), String> {
+    let fields: Vec<String> = record.split('\t').map(unescape).collect();
+
+    match (fields[0].as_str(), fields.len()) {
+        ("run", 6) | ("run", 7) => {
+            let id = match fields[1].parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => return Err(format!("invalid run id: {}", fields[1])),
+            };
+            *key = Some(HistoryKey { id: id, set: fields[2].clone(), plan: fields[3].clone(), uuid: fields[4].clone() });
+            run.start_time = read_time(&fields[5])?;
+            run.requester = fields.get(6).cloned().unwrap_or_default();
+        },
+        ("plan", 3) | ("plan", 4) => {
+            run.plan_hash = fields[1].clone();
+            run.plan_snapshot = fields[2].clone();
+            run.plan_file = fields.get(3).cloned().unwrap_or_default();
+        },
+        ("param", 3) => run.params.push((fields[1].clone(), fields[2].clone())),
+        ("rerun", 2) => match fields[1].parse::<u32>() {
+            Ok(original) => run.rerun_of = Some(original),
+            Err(_) => return Err(format!("invalid run id: {}", fields[1])),
+        },
+        ("approval", 6) => run.approvals.push(ApprovalRecord {
+            step: fields[1].clone(),
+            approved: fields[2] == "approved",
+            by: fields[3].clone(),
+            time: read_time(&fields[4])?,
+            comment: fields[5].clone(),
+        }),
+        ("log", 5) => run.log.push(LogEntry {
+            timestamp: read_time(&fields[1])?,
+            stream: StepOutputType::from_str(&fields[2])?,
+            step: fields[3].clone(),
+            text: fields[4].clone(),
+        }),
+        ("step", 8) => {
+            let step = StepRecord {
+                name: fields[1].clone(),
+                step_type: StepType::from_str(&fields[2])?,
+                status: match fields[3].is_empty() {
+                    true => None,
+                    false => Some(StepStatus::from_str(&fields[3])?),
+                },
+                exit_code: fields[4].parse::<i32>().ok(),
+                attempt: fields[5].parse::<u32>().unwrap_or(0),
+                start_time: read_time(&fields[6]).ok(),
+                end_time: read_time(&fields[7]).ok(),
+            };
+
+            match run.steps.iter_mut().find(|s| s.name == step.name) {
+                Some(current) => *current = step,
+                None => run.steps.push(step),
+            }
+        },
+        ("end", 3) => {
+            run.end_time = Some(read_time(&fields[1])?);
+            run.status = Some(fields[2].clone());
+        },
+        _ => return Err(format!("invalid record: {}", record)),
+    }
+
+    return Ok(());
+}
+
+/// Read a time field of journal
+fn read_time(text: &str) -> Result<DateTime<Local>, String> {
+    match parse_time(text) {
+        Some(time) => Ok(time),
+        None => Err(format!("invalid time: {}", text)),
+    }
+}
+
+/// Find the run in history, change it, then write the returned records into its journal
+///
+/// Journal of run is locked before history is released, so the order of records is the same as in the memory,
+/// but history is not locked while the file is written.
+fn update_run<F>(id: u32, func: F)
+where F: FnOnce(&mut RunRecord) -> Vec<String> {
+    let journal;
+    let mut file;
+    let records;
+
+    {
+        let mut history = HISTORY.write().unwrap();
+        let history = match &mut *history {
+            Some(hist) => hist,
+            None => {
+                eprintln!("Failed to write history");
+                return;
+            }
+        };
+
+        let key = HistoryKey { id: id, set: String::new(), plan: String::new(), uuid: String::new() };
+        let run = match history.get_mut(&key) {
+            Some(run) => run,
+            None => return,
+        };
+
+        records = func(run);
+        journal = journal_writer(id);
+        file = journal.lock().unwrap();
+    }
+
+    write_records(id, &mut file, &records, false);
+}
+
+/// Return with the writer of run's journal, file is opened when the first record is written
+fn journal_writer(id: u32) -> Arc<Mutex<Option<File>>> {
+    let mut journals = JOURNALS.lock().unwrap();
+    return journals.entry(id).or_insert_with(|| Arc::new(Mutex::new(None))).clone();
+}
+
+/// Append records to the end of a journal file, it is opened if it is not opened yet
+///
+/// Only new run can create the file, so nothing is written, if the run's history has already been dumped.
+fn write_records(id: u32, file: &mut Option<File>, records: &[String], create: bool) {
+    let dir = match journal_dir() {
+        Some(dir) => dir,
+        None => return,
+    };
+
+    let path = dir.join(format!("{}.journal", id));
+
+    if file.is_none() {
+        if !create && !path.exists() {
+            return;
+        }
+
+        let opened = OpenOptions::new()
+            .create(create)
+            .append(true)
+            .open(&path);
+
+        match opened {
+            Ok(opened) => *file = Some(opened),
+            Err(e) => {
+                eprintln!("Failed to open journal {}: {}", path.display(), e);
+                return;
+            }
+        }
+    }
+
+    let mut content = String::new();
+    for record in records {
+        content += record;
+        content += "\n";
+    }
+
+    if let Some(file) = file {
+        if let Err(e) = file.write_all(content.as_bytes()) {
+            eprintln!("Failed to write journal {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// Escape the characters which would break the record format, `\r` too, because it is removed from the end of line at reading
+fn escape(text: &str) -> String {
+    return text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r");
+}
+
+/// Restore the escaped characters
+fn unescape(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        for text in ["", "plain text", "tab\there", "line\nnext\r\n", "back\\slash \\t \\n \\", "\\\t\n\\\\"] {
+            let escaped = escape(text);
+            assert!(!escaped.contains('\t') && !escaped.contains('\n') && !escaped.contains('\r'), "escaped: {:?}", escaped);
+            assert_eq!(unescape(&escaped), text);
+        }
+    }
+
+    #[test]
+    fn carriage_return_round_trip() {
+        let time = format_time(&Local::now());
+        let content = [
+            format!("run\t7\tset\tplan\tuuid\t{}\tadmin", time),
+            format!("log\t{}\tI\ts1\t{}", time, escape("progress 50%\r")),
+            format!("log\t{}\tI\ts1\t{}", time, escape("\rprogress\r100%\r\n")),
+        ].join("\n");
+
+        let (_, run, errors) = read_journal(&content).unwrap();
+
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(run.log[0].text, "progress 50%\r");
+        assert_eq!(run.log[1].text, "\rprogress\r100%\r\n");
+    }
+
+    #[test]
+    fn invalid_records_are_skipped() {
+        let time = format_time(&Local::now());
+        let content = [
+            format!("run\t7\tset\tplan\tuuid\t{}\tadmin", time),
+            format!("log\t{}\tI\ts1\t{}", time, escape("first\tline")),
+            String::from("log\tnot a time\tI\ts1\tlost"),
+            String::from("unknown\trecord"),
+            format!("end\t{}\tOk", time),
+        ].join("\n");
+
+        let (key, run, errors) = read_journal(&content).unwrap();
+
+        assert_eq!(key.id, 7);
+        assert_eq!(run.requester, "admin");
+        assert_eq!(run.log.len(), 1);
+        assert_eq!(run.log[0].text, "first\tline");
+        assert_eq!(run.status.as_deref(), Some("Ok"));
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("record in line 3 is skipped"));
+        assert!(errors[1].starts_with("record in line 4 is skipped"));
+    }
+
+    #[test]
+    fn run_record_is_required() {
+        assert!(read_journal("end\t2024-01-01 00:00:00\tOk\n").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Locks of plan runs
+//!
+//! A run of a plan with `concurrency="single"` or `"queue"` holds the lock of its plan (`plan <set>/<plan>`),
+//! and if plan has `lock` attribute, it also holds the named lock (`lock <name>`). Runs which need a lock,
+//! that is held by other run, are either rejected or they wait. Waiting runs get the locks in the order
+//! of their IDs, so a run is not overtaken by a later one.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use hephaestus_plan::structs::enums::Concurrency;
+use hephaestus_plan::structs::plan::Plan;
+
+use crate::PLAN_LOCKS;
+use crate::PLAN_LOCKS_CV;
+
+/// How often a waiting run checks that it has been cancelled
+const CANCEL_CHECK: Duration = Duration::from_secs(1);
+
+/// Locks which are held and waited by runs
+/// - held => Lock name and ID of run which holds it
+/// - waiting => ID of waiting run and the locks what it needs
+pub struct LockTable {
+    held: BTreeMap<String, u32>,
+    waiting: BTreeMap<u32, Vec<String>>,
+}
+
+impl LockTable {
+    pub const fn new() -> LockTable {
+        return LockTable {
+            held: BTreeMap::new(),
+            waiting: BTreeMap::new(),
+        }
+    }
+
+    /// Return with the first lock which prevents the run to start and the ID of run which has it
+    ///
+    /// Lock is not available if it is held or an earlier run waits for it.
+    fn blocker(&self, id: u32, locks: &[String]) -> Option<(String, u32)> {
+        for lock in locks {
+            if let Some(holder) = self.held.get(lock) {
+                if *holder != id {
+                    return Some((lock.clone(), *holder));
+                }
+            }
+        }
+
+        for (waiting_id, waiting_locks) in self.waiting.range(..id) {
+            if let Some(lock) = locks.iter().find(|lock| waiting_locks.contains(lock)) {
+                return Some((lock.clone(), *waiting_id));
+            }
+        }
+
+        return None;
+    }
+}
+
+/// Locks held by a run, they are released when it is dropped
+pub struct PlanLock {
+    id: u32,
+    locks: Vec<String>,
+}
+
+impl Drop for PlanLock {
+    fn drop(&mut self) {
+        let mut table = PLAN_LOCKS.lock().unwrap();
+        for lock in &self.locks {
+            if table.held.get(lock) == Some(&self.id) {
+                table.held.remove(lock);
+            }
+        }
+        PLAN_LOCKS_CV.notify_all();
+    }
+}
+
+/// Result of lock request
+/// - Acquired => Run has the locks, it can start
+/// - Waiting => Run is registered as waiting, lock and the run which prevents the start are returned
+/// - Rejected => Run cannot start and it must not wait, lock and the run which has it are returned
+pub enum LockRequest {
+    Acquired(PlanLock),
+    Waiting(String, u32),
+    Rejected(String, u32),
+}
+
+/// Names of locks what a run of the plan needs
+pub fn lock_names(set: &str, plan_name: &str, plan: &Plan) -> Vec<String> {
+    let mut locks: Vec<String> = Vec::new();
+
+    if plan.concurrency != Concurrency::Allow {
+        locks.push(format!("plan {}/{}", set, plan_name));
+    }
+
+    if let Some(lock) = &plan.lock {
+        locks.push(format!("lock {}", lock));
+    }
+
+    return locks;
+}
+
+/// Try to get the locks of run, if it is not possible then run is rejected or registered as waiting by its concurrency
+pub fn request(id: u32, locks: Vec<String>, concurrency: Concurrency) -> LockRequest {
+    let mut table = PLAN_LOCKS.lock().unwrap();
+
+    match table.blocker(id, &locks) {
+        None => {
+            for lock in &locks {
+                table.held.insert(lock.clone(), id);
+            }
+            return LockRequest::Acquired(PlanLock { id: id, locks: locks });
+        },
+        Some((lock, holder)) if concurrency == Concurrency::Single => return LockRequest::Rejected(lock, holder),
+        Some((lock, holder)) => {
+            table.waiting.insert(id, locks);
+            return LockRequest::Waiting(lock, holder);
+        },
+    }
+}
+
+/// Wait until a waiting run gets its locks, None is returned if run is cancelled meanwhile
+pub fn wait(id: u32, cancel: &AtomicBool) -> Option<PlanLock> {
+    let mut table = PLAN_LOCKS.lock().unwrap();
+
+    loop {
+        let locks = match table.waiting.get(&id) {
+            Some(locks) => locks.clone(),
+            None => return None,
+        };
+
+        if cancel.load(Ordering::SeqCst) {
+            table.waiting.remove(&id);
+            PLAN_LOCKS_CV.notify_all();
+            return None;
+        }
+
+        if table.blocker(id, &locks).is_none() {
+            table.waiting.remove(&id);
+            for lock in &locks {
+                table.held.insert(lock.clone(), id);
+            }
+            return Some(PlanLock { id: id, locks: locks });
+        }
+
+        table = PLAN_LOCKS_CV.wait_timeout(table, CANCEL_CHECK).unwrap().0;
+    }
+}
+
+/// Readable name of lock for messages
+pub fn describe(lock: &str) -> String {
+    match lock.strip_prefix("lock ") {
+        Some(name) => format!("lock '{}'", name),
+        None => String::from(lock),
+    }
+}
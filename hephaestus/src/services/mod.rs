@@ -1,3 +1,9 @@
 pub mod grpc;
-pub mod parser;
-pub mod hermes_client;
\ No newline at end of file
+pub mod hermes_client;
+pub mod executor;
+pub mod history;
+pub mod archive;
+pub mod runner;
+pub mod scheduler;
+pub mod locks;
+pub mod approval;
\ No newline at end of file
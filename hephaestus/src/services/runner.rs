@@ -0,0 +1,427 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Instant;
+
+use hephaestus_plan::structs::plan::Plan;
+use hephaestus_plan::structs::enums::{StepOutputType, StepStatus, StepType};
+
+use crate::structs::historey_key::HistoryKey;
+
+use crate::GLOBAL_CONFIG;
+use crate::HISTORY;
+use crate::RUNNING_PLANS;
+use crate::LAST_RUN_ID;
+
+use super::executor::PlannedStep;
+use super::history::write_message;
+use super::locks::{self, LockRequest};
+
+/// Reasons why a plan could not be started
+/// - Internal => Something is wrong on server side or in the plan file
+/// - InvalidArgument => Specified parameters are not correct
+/// - Rejected => Plan cannot be started in its current state, e.g.: it is already running and its concurrency does not allow more runs
+/// - NotFound => Run which should be run again is not found
+pub enum StartError {
+    Internal(String),
+    InvalidArgument(String),
+    Rejected(String),
+    NotFound(String),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::Internal(e) => write!(f, "{}", e),
+            StartError::InvalidArgument(e) => write!(f, "{}", e),
+            StartError::Rejected(e) => write!(f, "{}", e),
+            StartError::NotFound(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// How a failed run is run again:
+/// - From => Specified step and every step which depends on it are run, others keep their original status
+/// - Failed => Steps which did not end with OK and their dependants are run, other successful steps are kept
+#[derive(Eq, PartialEq, Clone, Copy)]
+pub enum RerunMode {
+    From,
+    Failed,
+}
+
+/// Original run of a rerun
+/// - id => ID of the original run
+/// - snapshot => Plan file content, which was executed in the original run
+/// - file_name => Name of the plan file of original run
+/// - kept_steps => Steps which are not run again with their original status
+struct Origin {
+    id: u32,
+    snapshot: String,
+    file_name: String,
+    kept_steps: HashMap<String, StepStatus>,
+}
+
+/// Start a new run of plan in the background
+///
+/// Run ID is allocated and the run is recorded in the history, even if plan cannot be started,
+/// so the reason of failure can be checked later.
+pub fn start_plan(set: &str, plan_name: &str, params: &HashMap<String, String>, requester: String) -> Result<HistoryKey, StartError> {
+    return start(set, plan_name, params, requester, None);
+}
+
+/// Run an ended run again, with the same plan content and parameters
+///
+/// Plan is taken from the snapshot of original run, so changes of the plan file since then are not applied.
+pub fn rerun_plan(id: u32, from_step: &str, mode: RerunMode, requester: String) -> Result<HistoryKey, StartError> {
+    let (key, run) = {
+        let history = HISTORY.read().unwrap();
+        let history = match &*history {
+            Some(history) => history,
+            None => return Err(StartError::Internal(String::from("History is not initialized yet"))),
+        };
+
+        let key = HistoryKey { id: id, set: String::new(), plan: String::new(), uuid: String::new() };
+        match history.get_key_value(&key) {
+            Some((key, run)) => (key.clone(), run.clone()),
+            None => return Err(StartError::NotFound(String::from("Specified id is not found in online history"))),
+        }
+    };
+
+    if run.status.is_none() {
+        return Err(StartError::Rejected(String::from("Original run is still running")));
+    }
+
+    if run.plan_snapshot.is_empty() {
+        return Err(StartError::Rejected(String::from("Original run has no plan snapshot, it cannot be run again")));
+    }
+
+    // Runs before TOML and YAML plans have not recorded the file name, they were in tag or legacy format
+    let file_name = match run.plan_file.is_empty() {
+        true => format!("{}.conf", key.plan),
+        false => run.plan_file.clone(),
+    };
+
+    let plan = match hephaestus_plan::parser::parse_plan(&run.plan_snapshot, &file_name) {
+        Ok(plan) => plan,
+        Err(e) => return Err(StartError::Rejected(format!("Plan of original run is invalid: {}", e))),
+    };
+
+    let original_status = |name: &str| {
+        run.steps.iter()
+            .find(|step| step.name == name)
+            .and_then(|step| step.status.clone())
+            .unwrap_or(StepStatus::NotRun)
+    };
+
+    let rerun: Vec<String> = match mode {
+        RerunMode::From => {
+            if !plan.steps.iter().any(|step| step.step_name == from_step) {
+                return Err(StartError::InvalidArgument(format!("Step is not found in the plan: {}", from_step)));
+            }
+            descendants(&plan, from_step)
+        },
+        RerunMode::Failed => {
+            if !from_step.is_empty() {
+                return Err(StartError::InvalidArgument(String::from("Step cannot be specified in failed mode")));
+            }
+            let mut rerun: Vec<String> = Vec::new();
+            for step in plan.steps.iter().filter(|step| original_status(&step.step_name) != StepStatus::Ok) {
+                for name in descendants(&plan, &step.step_name) {
+                    if !rerun.contains(&name) {
+                        rerun.push(name);
+                    }
+                }
+            }
+            rerun
+        },
+    };
+
+    // Finally steps without parent belong to the end of plan, so they are always run again
+    let kept_steps: HashMap<String, StepStatus> = plan.steps.iter()
+        .filter(|step| !rerun.contains(&step.step_name))
+        .filter(|step| !(step.step_type == StepType::Finally && step.parents.is_empty()))
+        .map(|step| (step.step_name.clone(), original_status(&step.step_name)))
+        .collect();
+
+    let params: HashMap<String, String> = run.params.iter().cloned().collect();
+    let origin = Origin {
+        id: id,
+        snapshot: run.plan_snapshot.clone(),
+        file_name: file_name,
+        kept_steps: kept_steps,
+    };
+
+    return start(&key.set, &key.plan, &params, requester, Some(origin));
+}
+
+/// Names of the step and every step which depends on it directly or indirectly
+fn descendants(plan: &Plan, name: &str) -> Vec<String> {
+    let mut result: Vec<String> = vec![String::from(name)];
+    let mut index = 0;
+
+    while index < result.len() {
+        let parent = result[index].clone();
+        for step in &plan.steps {
+            if step.parents.contains(&parent) && !result.contains(&step.step_name) {
+                result.push(step.step_name.clone());
+            }
+        }
+        index += 1;
+    }
+
+    return result;
+}
+
+/// Resolve the plan with the parameters and tell which steps would run in which order, nothing is started
+///
+/// Assumed statuses (e.g.: `step02` => `nok`) are used as the result of the steps instead of OK,
+/// so it can be checked which recovery steps would be fired.
+pub fn dry_run(set: &str, plan_name: &str, params: &HashMap<String, String>, assume: &HashMap<String, String>) -> Result<(Vec<PlannedStep>, StepStatus), StartError> {
+    let path = match hephaestus_plan::parser::find_plan_file(&Path::new(&rule_dir()?).join(set), plan_name) {
+        Ok(path) => path,
+        Err(e) => return Err(StartError::Rejected(e)),
+    };
+
+    let mut plan = match hephaestus_plan::parser::collect_steps(&path) {
+        Ok(plan) => plan,
+        Err(e) => return Err(StartError::Internal(format!("Failed to parse file: {} {}", path.display(), e))),
+    };
+
+    if let Err(e) = plan.resolve_params(params) {
+        return Err(StartError::InvalidArgument(format!("Invalid parameters: {}", e)));
+    }
+
+    let mut assumed: HashMap<String, StepStatus> = HashMap::new();
+    for (name, status) in assume {
+        if !plan.steps.iter().any(|step| &step.step_name == name) {
+            return Err(StartError::InvalidArgument(format!("Step is not found in the plan: {}", name)));
+        }
+
+        let status = match &status.to_lowercase()[..] {
+            "ok" => StepStatus::Ok,
+            "nok" => StepStatus::Nok,
+            "failed" => StepStatus::Failed,
+            "timeout" | "timed_out" => StepStatus::TimedOut,
+            _ => return Err(StartError::InvalidArgument(format!("Assumed status must be ok, nok, failed or timeout: {}", status))),
+        };
+        assumed.insert(name.clone(), status);
+    }
+
+    return Ok(super::executor::simulate(&plan, &assumed));
+}
+
+/// Directory of plan sets from config
+fn rule_dir() -> Result<String, StartError> {
+    let config = GLOBAL_CONFIG.read().unwrap();
+    let config = match &*config {
+        Some(config) => config,
+        None => return Err(StartError::Internal(String::from("Configuration is not available"))),
+    };
+    match config.get("plan.rule_dir") {
+        Some(dir) => Ok(dir.clone()),
+        None => Err(StartError::Internal(String::from("Rule directory is not specified in config"))),
+    }
+}
+
+/// Allocate a new run, then start it in the background
+fn start(set: &str, plan_name: &str, params: &HashMap<String, String>, requester: String, origin: Option<Origin>) -> Result<HistoryKey, StartError> {
+    let rule_dir = rule_dir()?;
+
+    // First we need to figure out what is the next id and allocate a new output list in it
+    let run_key: HistoryKey = {
+        // ID is allocated from the persisted sequence, so it is not reused after dump or restart
+        let next_id = {
+            let mut last_id = LAST_RUN_ID.lock().unwrap();
+            let next_id = *last_id + 1;
+
+            if let Err(e) = super::history::save_sequence(next_id) {
+                return Err(StartError::Internal(e));
+            }
+
+            *last_id = next_id;
+            next_id
+        };
+
+        let key = HistoryKey {
+            id: next_id,
+            set: String::from(set),
+            plan: String::from(plan_name),
+            uuid: uuid::Uuid::new_v4().to_string(),
+        };
+
+        if let Err(e) = super::history::create_run(&key, requester) {
+            return Err(StartError::Internal(e));
+        }
+
+        key
+    };
+    let next_id = run_key.id;
+
+    let set_dir = Path::new(&rule_dir).join(set);
+
+    // Snapshot is taken about the exact content, what is executed, so it can be checked later even if file is changed
+    let (content, file_name) = match &origin {
+        Some(origin) => {
+            super::history::write_rerun_of(next_id, origin.id);
+            write_message(next_id, format!("----> {}/{} => Rerun of run {}, plan is taken from its snapshot", set, plan_name, origin.id), StepOutputType::Info);
+            (origin.snapshot.clone(), origin.file_name.clone())
+        },
+        None => {
+            let path = match hephaestus_plan::parser::find_plan_file(&set_dir, plan_name) {
+                Ok(path) => path,
+                Err(e) => {
+                    write_message(next_id, format!("----> {}/{} => Failed to find the plan: {}", set, plan_name, e), StepOutputType::Error);
+                    finish_run(next_id, set, plan_name, String::from("Failed"));
+                    return Err(StartError::Rejected(e));
+                },
+            };
+
+            match hephaestus_plan::parser::read_plan_file(&path) {
+                Ok(content) => (content, hephaestus_plan::parser::file_name(&path)),
+                Err(e) => {
+                    write_message(next_id, format!("----> {}/{} => Failed to read the plan: {}", set, plan_name, e), StepOutputType::Error);
+                    finish_run(next_id, set, plan_name, String::from("Failed"));
+                    return Err(StartError::Internal(format!("Failed to read file: {} {}", path.display(), e)));
+                },
+            }
+        },
+    };
+    super::history::write_snapshot(next_id, file_name.clone(), content.clone());
+    super::history::write_params(next_id, params);
+
+    let mut plan = match hephaestus_plan::parser::parse_plan(&content, &file_name) {
+        Ok(plan) => plan,
+        Err(e) => {
+            write_message(next_id, format!("----> {}/{} => Failed to parse the plan: {}", set, plan_name, e), StepOutputType::Error);
+            finish_run(next_id, set, plan_name, String::from("Failed"));
+            return Err(StartError::Internal(format!("Failed to parse file: {} {}", set_dir.join(&file_name).display(), e)));
+        },
+    };
+
+    // Parameters are verified before anything is started
+    if let Err(e) = plan.resolve_params(params) {
+        write_message(next_id, format!("----> {}/{} => Invalid parameters: {}", set, plan_name, e), StepOutputType::Error);
+        finish_run(next_id, set, &plan.id, String::from("Failed"));
+        return Err(StartError::InvalidArgument(format!("Invalid parameters: {}", e)));
+    }
+
+    if let Some(origin) = origin {
+        plan.kept_steps = origin.kept_steps;
+    }
+
+    // Run gets the locks of its plan and lock group now, or it waits for them in the background
+    let lock = match locks::request(next_id, locks::lock_names(set, plan_name, &plan), plan.concurrency) {
+        LockRequest::Acquired(lock) => Some(lock),
+        LockRequest::Waiting(lock, holder) => {
+            write_message(next_id, format!("----> {}/{} => Waiting for {}, it is held or requested earlier by run {}", set, plan_name, locks::describe(&lock), holder), StepOutputType::Info);
+            None
+        },
+        LockRequest::Rejected(lock, holder) => {
+            let reason = format!("{} is held by run {}", locks::describe(&lock), holder);
+            write_message(next_id, format!("----> {}/{} => Rejected, {}", set, plan_name, reason), StepOutputType::Error);
+            finish_run(next_id, set, &plan.id, String::from("Rejected"));
+            return Err(StartError::Rejected(format!("Plan is not started, {}", reason)));
+        },
+    };
+
+    write_message(next_id, format!("----> {}/{} => Plan has initialized, uuid: {}", set, plan_name, run_key.uuid), StepOutputType::Info);
+
+    let mut plan_info: (u32, Plan, String) = (next_id, plan, String::from(set));
+
+    println!("Scheduling {}({})...", plan_info.1.id, plan_info.0);
+
+    // Register the plan as running, so it can be cancelled
+    let cancel = Arc::new(AtomicBool::new(false));
+    {
+        let mut running = RUNNING_PLANS.lock().unwrap();
+        running.insert(plan_info.0, cancel.clone());
+    }
+
+    // Start batch in the background
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            // Lock is held until the end of run, then it is released by dropping it
+            let _lock = match lock {
+                Some(lock) => lock,
+                None => {
+                    let waiting = Instant::now();
+                    match locks::wait(plan_info.0, &cancel) {
+                        Some(lock) => {
+                            write_message(plan_info.0, format!("----> Locks are acquired after {}s waiting", waiting.elapsed().as_secs()), StepOutputType::Info);
+                            lock
+                        },
+                        None => {
+                            write_message(plan_info.0, String::from("----> Plan is cancelled while it was waiting for lock"), StepOutputType::Error);
+                            finish_run(plan_info.0, &plan_info.2, &plan_info.1.id, String::from("Cancelled"));
+
+                            let mut running = RUNNING_PLANS.lock().unwrap();
+                            running.remove(&plan_info.0);
+                            return;
+                        },
+                    }
+                },
+            };
+
+            super::executor::run_plan(plan_info.0, &mut plan_info.1, cancel);
+
+            write_message(plan_info.0, format!("----> Plan is ended, overall status: {:?}", plan_info.1.status), StepOutputType::Info);
+            finish_run(plan_info.0, &plan_info.2, &plan_info.1.id, format!("{:?}", plan_info.1.status));
+
+            {
+                let mut running = RUNNING_PLANS.lock().unwrap();
+                running.remove(&plan_info.0);
+            }
+        });
+    });
+
+    // Batch is running in the backgorund
+    return Ok(run_key);
+}
+
+/// Record the overall status of an ended run and send it to Hermes, if it is enabled
+///
+/// Every ended run is reported, also the ones which could not be started or were cancelled while waiting for lock.
+/// Plan ID is the ID of the plan tag, or the name of plan if the file could not be parsed.
+fn finish_run(id: u32, set: &str, plan_id: &str, status: String) {
+    super::history::end_run(id, status.clone());
+
+    let tx = match &*crate::HERMES_TX.lock().unwrap() {
+        Some(tx) => tx.clone(),
+        None => return,
+    };
+    let name = format!("{}/{}/{}", set, plan_id, id);
+
+    match tokio::runtime::Handle::try_current() {
+        // It is called from gRPC handlers too, they cannot wait, so status is sent by a task
+        Ok(handle) => {
+            handle.spawn(async move {
+                if let Err(e) = tx.send((name, status)).await {
+                    eprintln!("Failed to send status of run {} to Hermes: {}", id, e);
+                }
+            });
+        },
+        // Run thread waits until Hermes client has place for the status
+        Err(_) => {
+            if let Err(e) = tx.blocking_send((name, status)) {
+                eprintln!("Failed to send status of run {} to Hermes: {}", id, e);
+            }
+        },
+    }
+}
+
+/// Check that any run of the plan is still running
+pub fn is_running(set: &str, plan: &str) -> bool {
+    let history = HISTORY.read().unwrap();
+    let history = match &*history {
+        Some(history) => history,
+        None => return false,
+    };
+
+    return history.iter().any(|(key, run)| key.set == set && key.plan == plan && run.status.is_none());
+}
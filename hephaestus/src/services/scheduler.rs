@@ -0,0 +1,182 @@
+//! Built-in scheduler of plans
+//!
+//! Plans which have `schedule` attribute are collected from `plan.rule_dir` periodically, so changes of
+//! plan files are picked up without restart. When a schedule fires, the plan is started with its default
+//! parameter values, requester of the run is `scheduler`. Fire times which were missed while server was
+//! not running are not caught up.
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+
+use hephaestus_plan::structs::enums::Overlap;
+use hephaestus_plan::structs::schedule::ScheduledPlan;
+use hephaestus_plan::parser::{collect_steps, find_plan_file, is_plan_file};
+
+use crate::GLOBAL_CONFIG;
+use crate::SCHEDULES;
+
+use super::runner::{is_running, start_plan};
+
+/// How often plan files are read again to refresh the schedules
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often schedules are checked
+const TICK: Duration = Duration::from_secs(1);
+
+/// Run the scheduler, it never returns
+pub fn run() {
+    let mut last_reload: Option<Instant> = None;
+
+    loop {
+        if last_reload.map_or(true, |time| time.elapsed() >= RELOAD_INTERVAL) {
+            reload();
+            last_reload = Some(Instant::now());
+        }
+
+        fire_due();
+        std::thread::sleep(TICK);
+    }
+}
+
+/// Collect scheduled plans from the plan directory
+///
+/// Plans whose schedule has not changed keep their next fire time and queued state.
+pub fn reload() {
+    let rule_dir = {
+        let config = GLOBAL_CONFIG.read().unwrap();
+        match &*config {
+            Some(config) => match config.get("plan.rule_dir") {
+                Some(dir) => dir.clone(),
+                None => return,
+            },
+            None => return,
+        }
+    };
+
+    let now = Local::now();
+    let mut found: Vec<ScheduledPlan> = Vec::new();
+
+    let sets = match fs::read_dir(&rule_dir) {
+        Ok(sets) => sets,
+        Err(e) => {
+            eprintln!("Scheduler failed to read {}: {}", rule_dir, e);
+            return;
+        }
+    };
+
+    for set in sets.flatten() {
+        let set_path = set.path();
+        if !set_path.is_dir() {
+            continue;
+        }
+
+        let plans = match fs::read_dir(&set_path) {
+            Ok(plans) => plans,
+            Err(_) => continue,
+        };
+
+        for plan in plans.flatten() {
+            let path = plan.path();
+            if !is_plan_file(&path) {
+                continue;
+            }
+
+            // Plan which is defined in more files cannot be executed, so it is not scheduled either
+            match find_plan_file(&set_path, &file_stem(&path)) {
+                Ok(found) if found == path => (),
+                _ => continue,
+            }
+
+            // Invalid plans are not scheduled, their errors are reported when they are executed or validated
+            let parsed = match collect_steps(&path) {
+                Ok(parsed) => parsed,
+                Err(_) => continue,
+            };
+
+            if let Some(schedule) = parsed.schedule {
+                found.push(ScheduledPlan {
+                    set: file_stem(&set_path),
+                    plan: file_stem(&path),
+                    next: schedule.next_after(&now),
+                    schedule: schedule,
+                    queued: false,
+                });
+            }
+        }
+    }
+
+    found.sort_by(|a, b| a.set.cmp(&b.set).then(a.plan.cmp(&b.plan)));
+
+    let mut schedules = SCHEDULES.lock().unwrap();
+
+    for plan in found.iter_mut() {
+        let old = schedules.iter().find(|old| old.set == plan.set && old.plan == plan.plan);
+        if let Some(old) = old {
+            if old.schedule.expression == plan.schedule.expression && old.schedule.timezone == plan.schedule.timezone {
+                plan.next = old.next;
+                plan.queued = old.queued && plan.schedule.overlap == Overlap::Queue;
+            }
+        }
+    }
+
+    *schedules = found;
+}
+
+/// Start the plans whose fire time has come, or which are queued and their previous run has ended
+///
+/// Schedules are locked only while the due plans are collected, plans are started after it is released.
+fn fire_due() {
+    let now = Local::now();
+    let mut due_plans: Vec<(String, String)> = Vec::new();
+
+    {
+        let mut schedules = SCHEDULES.lock().unwrap();
+
+        for plan in schedules.iter_mut() {
+            let due = plan.next.map_or(false, |next| next <= now);
+            if !due && !plan.queued {
+                continue;
+            }
+
+            if due {
+                plan.next = plan.schedule.next_after(&now);
+            }
+
+            if is_running(&plan.set, &plan.plan) {
+                match plan.schedule.overlap {
+                    Overlap::Skip => {
+                        if due {
+                            println!("Scheduled run of {}/{} is skipped, previous run is still running", plan.set, plan.plan);
+                        }
+                        continue;
+                    },
+                    Overlap::Queue => {
+                        if due && !plan.queued {
+                            println!("Scheduled run of {}/{} is queued, previous run is still running", plan.set, plan.plan);
+                        }
+                        plan.queued = true;
+                        continue;
+                    },
+                    Overlap::Allow => (),
+                }
+            }
+
+            plan.queued = false;
+            due_plans.push((plan.set.clone(), plan.plan.clone()));
+        }
+    }
+
+    for (set, plan) in due_plans {
+        match start_plan(&set, &plan, &Default::default(), String::from("scheduler")) {
+            Ok(key) => println!("Scheduled run of {}/{} is started, id: {}", set, plan, key.id),
+            Err(e) => eprintln!("Scheduled run of {}/{} is failed: {}", set, plan, e),
+        }
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    return path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+}
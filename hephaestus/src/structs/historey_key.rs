@@ -2,11 +2,14 @@ use std::fmt;
 use std::hash::Hash;
 
 /// This struct is the key for that Hashmap which stores the online log of Hephaestus plans
+/// 
+/// Id is never reused, uuid identifies the run uniquely also between different servers.
 #[derive(Clone)]
 pub struct HistoryKey {
     pub id: u32,
     pub set: String,
     pub plan: String,
+    pub uuid: String,
 }
 
 impl fmt::Display for HistoryKey {
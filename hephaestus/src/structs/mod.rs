@@ -1,5 +1,2 @@
-pub mod enums;
-pub mod plan;
-pub mod step;
-pub mod action;
-pub mod historey_key;
\ No newline at end of file
+pub mod historey_key;
+pub mod run;
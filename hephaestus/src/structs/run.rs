@@ -0,0 +1,178 @@
+use std::fmt;
+
+use chrono::{DateTime, Local, SecondsFormat};
+
+use hephaestus_plan::structs::enums::{StepOutputType, StepStatus, StepType};
+use hephaestus_plan::structs::step::Step;
+
+/// One line of a run's log
+/// - timestamp => When the line was written
+/// - stream => Info (stdout of command and messages) or error (stderr of command and error messages)
+/// - step => Name of step which has written the line, it is empty for the messages of plan
+/// - text => Content of line
+#[derive(Clone)]
+pub struct LogEntry {
+    pub timestamp: DateTime<Local>,
+    pub stream: StepOutputType,
+    pub step: String,
+    pub text: String,
+}
+
+impl LogEntry {
+    /// Create a new log entry with the current time
+    pub fn new(step: &str, stream: StepOutputType, text: String) -> LogEntry {
+        return LogEntry {
+            timestamp: Local::now(),
+            stream: stream,
+            step: String::from(step),
+            text: text,
+        }
+    }
+}
+
+/// Text view of log entry, this is how it is displayed and written into log files
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.timestamp.format("%Y-%m-%d %H:%M:%S");
+
+        if self.step.is_empty() {
+            return write!(f, "{} {} {}", time, self.stream, self.text);
+        }
+        return write!(f, "{} {} {}: {}", time, self.stream, self.step, self.text);
+    }
+}
+
+/// Status of a step within a run
+/// - status => Final status of step, it is None until step is not ended
+/// - exit_code => Exit code of the last attempt, None if command has not ended by itself
+/// - attempt => Number of the last attempt
+#[derive(Clone)]
+pub struct StepRecord {
+    pub name: String,
+    pub step_type: StepType,
+    pub status: Option<StepStatus>,
+    pub exit_code: Option<i32>,
+    pub attempt: u32,
+    pub start_time: Option<DateTime<Local>>,
+    pub end_time: Option<DateTime<Local>>,
+}
+
+impl StepRecord {
+    /// Create a record for a step which has not started yet
+    pub fn new(step: &Step) -> StepRecord {
+        return StepRecord {
+            name: step.step_name.clone(),
+            step_type: step.step_type.clone(),
+            status: None,
+            exit_code: None,
+            attempt: 0,
+            start_time: None,
+            end_time: None,
+        }
+    }
+
+    /// Readable state of step: waiting, running, waiting for approval or its final status
+    pub fn state(&self) -> String {
+        match (&self.status, self.start_time) {
+            (Some(status), _) => format!("{:?}", status),
+            (None, Some(_)) if self.step_type == StepType::Approval => String::from("WaitingApproval"),
+            (None, Some(_)) => String::from("Running"),
+            (None, None) => String::from("Waiting"),
+        }
+    }
+}
+
+/// Decision about an approval step
+/// - approved => Step is approved or rejected
+/// - by => Who has decided: subject of client certificate or address of peer
+/// - comment => Optional comment of decision
+#[derive(Clone)]
+pub struct ApprovalRecord {
+    pub step: String,
+    pub approved: bool,
+    pub by: String,
+    pub time: DateTime<Local>,
+    pub comment: String,
+}
+
+/// Everything what is recorded about a run of plan
+/// - status => Overall status of the run, it is None while run is in progress
+/// - requester => Who has started the run: subject of client certificate or address of peer
+/// - plan_hash => SHA-256 hash of the plan file content, which was executed
+/// - plan_snapshot => Copy of the plan file content, which was executed
+/// - plan_file => Name of the plan file, its extension tells the format of snapshot
+/// - params => Parameter values which were specified at execution
+/// - rerun_of => ID of the original run, if this is a rerun
+/// - approvals => Decisions about the approval steps of run
+#[derive(Clone)]
+pub struct RunRecord {
+    pub status: Option<String>,
+    pub start_time: DateTime<Local>,
+    pub end_time: Option<DateTime<Local>>,
+    pub requester: String,
+    pub plan_hash: String,
+    pub plan_snapshot: String,
+    pub plan_file: String,
+    pub params: Vec<(String, String)>,
+    pub rerun_of: Option<u32>,
+    pub approvals: Vec<ApprovalRecord>,
+    pub steps: Vec<StepRecord>,
+    pub log: Vec<LogEntry>,
+}
+
+impl RunRecord {
+    /// Create record for a run which is started now
+    pub fn new(requester: String) -> RunRecord {
+        return RunRecord {
+            status: None,
+            start_time: Local::now(),
+            end_time: None,
+            requester: requester,
+            plan_hash: String::new(),
+            plan_snapshot: String::new(),
+            plan_file: String::new(),
+            params: Vec::new(),
+            rerun_of: None,
+            approvals: Vec::new(),
+            steps: Vec::new(),
+            log: Vec::new(),
+        }
+    }
+
+    /// Readable overall state of run
+    pub fn state(&self) -> String {
+        match &self.status {
+            Some(status) => status.clone(),
+            None if self.steps.iter().any(|step| step.state() == "WaitingApproval") => String::from("WaitingApproval"),
+            None => String::from("Running"),
+        }
+    }
+
+    /// Text view of the log, same lines that are written into log files
+    pub fn lines(&self) -> Vec<String> {
+        return self.log.iter().map(|entry| entry.to_string()).collect();
+    }
+}
+
+/// Format time as it is used in the journal and in the gRPC messages
+pub fn format_time(time: &DateTime<Local>) -> String {
+    return time.to_rfc3339_opts(SecondsFormat::Millis, false);
+}
+
+/// Parse time which was formatted by `format_time`
+pub fn parse_time(text: &str) -> Option<DateTime<Local>> {
+    match DateTime::parse_from_rfc3339(text) {
+        Ok(time) => Some(time.with_timezone(&Local)),
+        Err(_) => None,
+    }
+}
+
+/// Elapsed time in milliseconds between start and end, if there is no end yet then it is measured till now
+pub fn duration_ms(start: &DateTime<Local>, end: &Option<DateTime<Local>>) -> u64 {
+    let end = match end {
+        Some(end) => *end,
+        None => Local::now(),
+    };
+
+    return (end - *start).num_milliseconds().max(0) as u64;
+}
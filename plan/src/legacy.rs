@@ -0,0 +1,246 @@
+//! Parser of the legacy key-value plan format
+//!
+//! Every line is an option of a step in `<step>.<option> = <value>` format, e.g.: `step01.desc = Stop Gitlab`.
+//! Options are the same like the attributes of the tag format, besides them:
+//! - `type` => Kind of step: `action`, `recovery`, `finally` or `approval`
+//! - `action` => Command of step, if it is specified more times, they are the lines of a script
+//!
+//! Lines begin with `#` are comments. Plan ID is the name of file without `.conf`, plan options cannot be specified.
+
+use crate::structs::action::Action;
+use crate::structs::enums::StepType;
+use crate::structs::plan::Plan;
+use crate::structs::step::Step;
+
+use super::lexer::{Location, ParseError};
+use super::parser::{check_graph, step_attribute, Attribute, ParentRef, REPEATABLE_ATTRS};
+
+/// Options of a step as they are written in the file, with the location of first one
+struct LegacyStep {
+    name: String,
+    location: Location,
+    options: Vec<Attribute>,
+}
+
+/// Check that the content is in legacy format: its first line, which is not empty or comment, is not a tag
+pub fn is_legacy(content: &str) -> bool {
+    match content.lines().map(|line| line.trim()).find(|line| !line.is_empty() && !line.starts_with('#')) {
+        Some(line) => !line.starts_with('<'),
+        None => false,
+    }
+}
+
+/// Process the content of a legacy plan file, errors are collected into `errors`
+pub fn parse_legacy(content: &str, file_name: &str, errors: &mut Vec<ParseError>) -> Plan {
+    let mut steps: Vec<LegacyStep> = Vec::new();
+
+    for (number, line) in content.lines().enumerate() {
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let location = Location { line: number + 1, col: line.len() - line.trim_start().len() + 1 };
+
+        let (key, value) = match text.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => {
+                errors.push(ParseError::new(location, format!("line must be in '<step>.<option> = <value>' format: {}", text)));
+                continue;
+            }
+        };
+
+        let (name, option) = match key.split_once('.') {
+            Some((name, option)) if !name.is_empty() && !option.is_empty() => (name, option),
+            _ => {
+                errors.push(ParseError::new(location, format!("key must be in '<step>.<option>' format: {}", key)));
+                continue;
+            }
+        };
+
+        let index = match steps.iter().position(|step| step.name == name) {
+            Some(index) => index,
+            None => {
+                steps.push(LegacyStep { name: String::from(name), location, options: Vec::new() });
+                steps.len() - 1
+            }
+        };
+
+        let step = &mut steps[index];
+        if !REPEATABLE_ATTRS.contains(&option) && option != "action" && step.options.iter().any(|o| o.0 == option) {
+            errors.push(ParseError::new(location, format!("{}.{} is specified more times", name, option)));
+            continue;
+        }
+        step.options.push((String::from(option), String::from(value), location));
+    }
+
+    let mut plan = Plan::new(String::from(file_name.strip_suffix(".conf").unwrap_or(file_name)), Vec::new());
+    let mut step_locations: Vec<Location> = Vec::new();
+    let mut parent_refs: Vec<ParentRef> = Vec::new();
+
+    for (index, legacy) in steps.iter().enumerate() {
+        let step = build_step(index, legacy, &mut parent_refs, errors);
+        plan.steps.push(step);
+        step_locations.push(legacy.location);
+    }
+
+    check_graph(&plan, &step_locations, &parent_refs, errors);
+
+    plan
+}
+
+/// Create a step from its options
+fn build_step(index: usize, legacy: &LegacyStep, parent_refs: &mut Vec<ParentRef>, errors: &mut Vec<ParseError>) -> Step {
+    let mut step = Step::new_empty();
+    step.step_name = legacy.name.clone();
+
+    // Type decides which options can be used, so it is processed first
+    if let Some((_, value, location)) = legacy.options.iter().find(|o| o.0 == "type") {
+        match &value[..] {
+            "action" | "step" => step.step_type = StepType::Action,
+            "recovery" => step.step_type = StepType::Recovery,
+            "finally" => step.step_type = StepType::Finally,
+            "approval" => step.step_type = StepType::Approval,
+            _ => errors.push(ParseError::new(*location, format!("{}.type must be action, recovery, finally or approval: {}", legacy.name, value))),
+        }
+    }
+
+    // Step which has none of the main options is most likely a typo in the name of step, e.g.: `fail03.parent` instead of `fail02.parent`
+    if !legacy.options.iter().any(|o| o.0 == "type" || o.0 == "desc" || o.0 == "action") {
+        let keys: Vec<String> = legacy.options.iter().map(|o| format!("{}.{}", legacy.name, o.0)).collect();
+        errors.push(ParseError::new(legacy.location, format!("step {} has no type, desc and action, only {} is specified", legacy.name, keys.join(", "))));
+        return step;
+    }
+
+    let mut cwd: Option<String> = None;
+    let mut command: Vec<&str> = Vec::new();
+
+    for (option, value, location) in &legacy.options {
+        match &option[..] {
+            "type" => (),
+            "action" => command.push(value),
+            "name" => errors.push(ParseError::new(*location, format!("unknown option '{}.{}'", legacy.name, option))),
+            _ => {
+                let known_parents = step.parents.len();
+                step_attribute(&mut step, option, value.clone(), *location, &mut cwd, errors);
+
+                for parent in &step.parents[known_parents..] {
+                    parent_refs.push((index, parent.clone(), *location));
+                }
+            },
+        }
+    }
+
+    if !command.is_empty() {
+        step.action = Some(Action::new(command.join("\n"), cwd));
+    }
+
+    if let Err(e) = step.validate() {
+        for line in e.lines() {
+            errors.push(ParseError::new(legacy.location, format!("{}: {}", legacy.name, line)));
+        }
+    }
+
+    step
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::tests::{assert_same_plan, errors};
+
+    #[test]
+    fn same_plan_as_tag_format() {
+        let legacy = "\
+# Create backup
+step01.desc = Create backup
+step01.type = action
+step01.cwd = /tmp
+step01.setenv = TARGET /backup
+step01.setenv = LEVEL 9
+step01.action = mkdir -p $TARGET
+step01.action = tar -czf $TARGET/etc.tar.gz /etc
+step01.timeout = 1h
+step01.retry = 2
+
+step02.desc = Clean up
+step02.type = finally
+step02.action = rm -f /tmp/lock
+
+fail01.desc = Report failure
+fail01.type = recovery
+fail01.parent = step01
+fail01.user = backup
+fail01.action = echo failed
+";
+        let tag = r#"
+<plan id="backup"/>
+<step name="step01" desc="Create backup" cwd="/tmp" setenv="TARGET /backup" setenv="LEVEL 9" timeout="1h" retry="2">
+mkdir -p $TARGET
+tar -czf $TARGET/etc.tar.gz /etc
+</step>
+<finally name="step02" desc="Clean up">rm -f /tmp/lock</finally>
+<recovery name="fail01" desc="Report failure" parent="step01" user="backup">echo failed</recovery>
+"#;
+
+        assert_same_plan(legacy, "backup.conf", tag);
+    }
+
+    #[test]
+    fn duplicated_keys() {
+        let content = "\
+step01.desc = First
+step01.type = action
+step01.action = echo 1
+  step01.desc = Again
+step01.type = recovery
+";
+
+        assert_eq!(errors(content, "backup.conf"), vec![
+            "backup.conf:4:3: step01.desc is specified more times",
+            "backup.conf:5:1: step01.type is specified more times",
+        ]);
+    }
+
+    #[test]
+    fn unknown_steps() {
+        let content = "\
+step01.desc = First
+step01.type = action
+step01.action = echo 1
+step01.parent = step09
+
+fail02.desc = Recovery
+fail02.type = recovery
+fail02.action = echo 2
+fail03.parent = step01
+";
+
+        assert_eq!(errors(content, "backup.conf"), vec![
+            "backup.conf:4:1: parent of step01 does not exist: step09",
+            "backup.conf:6:1: fail02: Recovery step must have parent!",
+            "backup.conf:9:1: step fail03 has no type, desc and action, only fail03.parent is specified",
+        ]);
+    }
+
+    #[test]
+    fn example_file() {
+        let content = include_str!("../../other/plans/test#2/test.something.else.conf");
+
+        assert_eq!(errors(content, "test.something.else.conf"), vec![
+            "test.something.else.conf:13:1: step03: Step type must be specified!",
+            "test.something.else.conf:14:1: step02.type is specified more times",
+            "test.something.else.conf:19:1: fail02: Recovery step must have parent!",
+            "test.something.else.conf:22:1: step fail03 has no type, desc and action, only fail03.parent is specified",
+        ]);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        assert_eq!(errors("step01.desc = First\nstep01 desc\n.desc = x\n", "backup.conf"), vec![
+            "backup.conf:1:1: step01: Step type must be specified!",
+            "backup.conf:1:1: step01: Action must be specified!",
+            "backup.conf:2:1: line must be in '<step>.<option> = <value>' format: step01 desc",
+            "backup.conf:3:1: key must be in '<step>.<option>' format: .desc",
+        ]);
+    }
+}
@@ -0,0 +1,420 @@
+use std::fmt;
+
+/// Tokens of the plan file format:
+/// - Open => Beginning of an open tag, e.g.: `<step`
+/// - Attr => Attribute within an open tag, e.g.: `name="step01"`
+/// - TagEnd => End of an open tag: `>`
+/// - SelfClose => End of an open tag without content: `/>`
+/// - Close => Close tag, e.g.: `</step>`
+/// - Text => Content of a raw tag (e.g.: command of step), as it is written in the file
+/// - Eof => End of file
+#[derive(PartialEq, Debug, Clone)]
+pub enum Token {
+    Open(String),
+    Attr(String, String),
+    TagEnd,
+    SelfClose,
+    Close(String),
+    Text(String),
+    Eof,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open(name) => write!(f, "'<{}'", name),
+            Token::Attr(name, _) => write!(f, "attribute '{}'", name),
+            Token::TagEnd => write!(f, "'>'"),
+            Token::SelfClose => write!(f, "'/>'"),
+            Token::Close(name) => write!(f, "'</{}>'", name),
+            Token::Text(_) => write!(f, "text"),
+            Token::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+/// Position of something in the plan file, both line and column start from 1
+#[derive(Clone, Copy, Debug)]
+pub struct Location {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Location {
+    /// Error which cannot be tied to a position, e.g. a wrong value in a TOML or YAML file
+    pub const NONE: Location = Location { line: 0, col: 0 };
+}
+
+/// Error with its location in the plan file
+#[derive(Debug)]
+pub struct ParseError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(location: Location, message: String) -> ParseError {
+        ParseError {
+            location,
+            message,
+        }
+    }
+}
+
+/// State of lexer, it decides how the next characters are read
+enum State {
+    Top,
+    InTag(String),
+    Raw(String),
+}
+
+/// Split the content of a plan file into tokens
+///
+/// Outside of tags, whitespaces are skipped and '#' begins a comment till the end of line.
+/// Content of raw tags (steps) is not tokenized, it is returned as one text token.
+pub struct Lexer<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    col: usize,
+    state: State,
+    raw_tags: &'a [&'a str],
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(content: &str, raw_tags: &'a [&'a str]) -> Lexer<'a> {
+        Lexer {
+            chars: content.chars().collect(),
+            pos: 0,
+            line: 1,
+            col: 1,
+            state: State::Top,
+            raw_tags,
+        }
+    }
+
+    /// Return with the next token and its location
+    pub fn next_token(&mut self) -> Result<(Token, Location), ParseError> {
+        match &self.state {
+            State::Top => self.next_top(),
+            State::InTag(_) => self.next_in_tag(),
+            State::Raw(tag) => {
+                let tag = tag.clone();
+                self.next_raw(&tag)
+            },
+        }
+    }
+
+    fn location(&self) -> Location {
+        Location { line: self.line, col: self.col }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        }
+        else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn read_name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                name.push(c);
+                self.bump();
+            }
+            else {
+                break;
+            }
+        }
+        name
+    }
+
+    /// Read tokens between the tags, only tags and comments can be here
+    fn next_top(&mut self) -> Result<(Token, Location), ParseError> {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                },
+                Some('#') => {
+                    while let Some(c) = self.bump() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                },
+                _ => break,
+            }
+        }
+
+        let location = self.location();
+
+        match self.peek() {
+            None => Ok((Token::Eof, location)),
+            Some('<') => {
+                self.bump();
+                let close = self.peek() == Some('/');
+                if close {
+                    self.bump();
+                }
+
+                let name = self.read_name();
+                if name.is_empty() {
+                    return Err(ParseError::new(location, String::from("tag name is missing after '<'")));
+                }
+
+                if close {
+                    if self.peek() != Some('>') {
+                        return Err(ParseError::new(self.location(), format!("'>' is expected after '</{}'", name)));
+                    }
+                    self.bump();
+                    return Ok((Token::Close(name), location));
+                }
+
+                self.state = State::InTag(name.clone());
+                Ok((Token::Open(name), location))
+            },
+            Some(c) => Err(ParseError::new(location, format!("unexpected character '{}' outside of tags", c))),
+        }
+    }
+
+    /// Read the attributes of an open tag, until its end
+    fn next_in_tag(&mut self) -> Result<(Token, Location), ParseError> {
+        while let Some(c) = self.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.bump();
+        }
+
+        let location = self.location();
+
+        match self.peek() {
+            None => Err(ParseError::new(location, String::from("tag is not closed before end of file"))),
+            Some('>') => {
+                self.bump();
+                let tag = match &self.state {
+                    State::InTag(tag) => tag.clone(),
+                    _ => String::new(),
+                };
+                self.state = match self.raw_tags.contains(&&tag[..]) {
+                    true => State::Raw(tag),
+                    false => State::Top,
+                };
+                Ok((Token::TagEnd, location))
+            },
+            Some('/') if self.peek_at(1) == Some('>') => {
+                self.bump();
+                self.bump();
+                self.state = State::Top;
+                Ok((Token::SelfClose, location))
+            },
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                let name = self.read_name();
+
+                while let Some(' ') | Some('\t') = self.peek() {
+                    self.bump();
+                }
+                if self.peek() != Some('=') {
+                    return Err(ParseError::new(self.location(), format!("'=' is expected after attribute '{}'", name)));
+                }
+                self.bump();
+                while let Some(' ') | Some('\t') = self.peek() {
+                    self.bump();
+                }
+
+                let value = self.read_quoted(&name)?;
+                Ok((Token::Attr(name, value), location))
+            },
+            Some(c) => Err(ParseError::new(location, format!("unexpected character '{}' in tag", c))),
+        }
+    }
+
+    /// Read a quoted value, both double and single quotes can be used
+    ///
+    /// Supported escape sequences: `\"`, `\'`, `\\`, `\n` and `\t`, other backslashes are kept as they are.
+    fn read_quoted(&mut self, name: &str) -> Result<String, ParseError> {
+        let start = self.location();
+        let quote = match self.peek() {
+            Some(q) if q == '"' || q == '\'' => q,
+            _ => return Err(ParseError::new(start, format!("value of attribute '{}' must be quoted", name))),
+        };
+        self.bump();
+
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(ParseError::new(start, format!("value of attribute '{}' is not closed", name))),
+                Some('\\') => {
+                    match self.peek() {
+                        Some('"') => value.push('"'),
+                        Some('\'') => value.push('\''),
+                        Some('\\') => value.push('\\'),
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        _ => {
+                            value.push('\\');
+                            continue;
+                        }
+                    }
+                    self.bump();
+                },
+                Some(c) if c == quote => break,
+                Some(c) => value.push(c),
+            }
+        }
+
+        Ok(value)
+    }
+
+    /// Read everything as it is until the close tag
+    fn next_raw(&mut self, tag: &str) -> Result<(Token, Location), ParseError> {
+        let location = self.location();
+        let close: Vec<char> = format!("</{}>", tag).chars().collect();
+        let mut text = String::new();
+
+        loop {
+            if self.pos >= self.chars.len() {
+                return Err(ParseError::new(location, format!("'</{}>' is missing", tag)));
+            }
+
+            if self.chars[self.pos..].starts_with(&close) {
+                break;
+            }
+
+            if let Some(c) = self.bump() {
+                text.push(c);
+            }
+        }
+
+        self.state = State::Top;
+        Ok((Token::Text(text), location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAW_TAGS: [&str; 1] = ["step"];
+
+    /// Read every token of content until end of file or the first error
+    fn tokens(content: &str) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+        let mut lexer = Lexer::new(content, &RAW_TAGS);
+        let mut tokens = Vec::new();
+
+        loop {
+            let (token, location) = lexer.next_token()?;
+            let eof = token == Token::Eof;
+            tokens.push((token, location.line, location.col));
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn error(content: &str) -> (usize, usize, String) {
+        match tokens(content) {
+            Ok(tokens) => panic!("error is expected, tokens: {:?}", tokens),
+            Err(e) => (e.location.line, e.location.col, e.message),
+        }
+    }
+
+    #[test]
+    fn tokens_with_locations() {
+        let content = "# comment <step>\n<plan id=\"test\"/>\n  <param name='x' default=\"1\" />\n";
+
+        assert_eq!(tokens(content).unwrap(), vec![
+            (Token::Open(String::from("plan")), 2, 1),
+            (Token::Attr(String::from("id"), String::from("test")), 2, 7),
+            (Token::SelfClose, 2, 16),
+            (Token::Open(String::from("param")), 3, 3),
+            (Token::Attr(String::from("name"), String::from("x")), 3, 10),
+            (Token::Attr(String::from("default"), String::from("1")), 3, 19),
+            (Token::SelfClose, 3, 31),
+            (Token::Eof, 4, 1),
+        ]);
+    }
+
+    #[test]
+    fn close_tag_and_spaces_around_equal_sign() {
+        assert_eq!(tokens("<plan id = \"a\"></plan>").unwrap(), vec![
+            (Token::Open(String::from("plan")), 1, 1),
+            (Token::Attr(String::from("id"), String::from("a")), 1, 7),
+            (Token::TagEnd, 1, 15),
+            (Token::Close(String::from("plan")), 1, 16),
+            (Token::Eof, 1, 23),
+        ]);
+    }
+
+    #[test]
+    fn quoted_values_and_escapes() {
+        let content = r#"<plan a="it's" b='say "hi"' c="q\"q\'q" d='\\ \n \t' e="\x"/>"#;
+        let values: Vec<Token> = tokens(content).unwrap().into_iter()
+            .map(|(token, _, _)| token)
+            .filter(|token| matches!(token, Token::Attr(_, _)))
+            .collect();
+
+        assert_eq!(values, vec![
+            Token::Attr(String::from("a"), String::from("it's")),
+            Token::Attr(String::from("b"), String::from("say \"hi\"")),
+            Token::Attr(String::from("c"), String::from("q\"q'q")),
+            Token::Attr(String::from("d"), String::from("\\ \n \t")),
+            Token::Attr(String::from("e"), String::from("\\x")),
+        ]);
+    }
+
+    #[test]
+    fn raw_text_is_kept_as_it_is() {
+        let content = "<step name=\"s\">\n  echo \"<b>\" # not comment\n</step>";
+
+        assert_eq!(tokens(content).unwrap(), vec![
+            (Token::Open(String::from("step")), 1, 1),
+            (Token::Attr(String::from("name"), String::from("s")), 1, 7),
+            (Token::TagEnd, 1, 15),
+            (Token::Text(String::from("\n  echo \"<b>\" # not comment\n")), 1, 16),
+            (Token::Close(String::from("step")), 3, 1),
+            (Token::Eof, 3, 8),
+        ]);
+    }
+
+    #[test]
+    fn unclosed_raw_tag() {
+        assert_eq!(error("<step name=\"s\">\necho hi\n"), (1, 16, String::from("'</step>' is missing")));
+    }
+
+    #[test]
+    fn unclosed_tag() {
+        assert_eq!(error("<plan id=\"a\"\n"), (2, 1, String::from("tag is not closed before end of file")));
+    }
+
+    #[test]
+    fn unclosed_value() {
+        assert_eq!(error("<plan id=\"a/>\n"), (1, 10, String::from("value of attribute 'id' is not closed")));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("<plan id=a/>"), (1, 10, String::from("value of attribute 'id' must be quoted")));
+        assert_eq!(error("<plan id/>"), (1, 9, String::from("'=' is expected after attribute 'id'")));
+        assert_eq!(error("\n  < plan/>"), (2, 3, String::from("tag name is missing after '<'")));
+        assert_eq!(error("</plan"), (1, 7, String::from("'>' is expected after '</plan'")));
+        assert_eq!(error("<plan id=\"a\" !/>"), (1, 14, String::from("unexpected character '!' in tag")));
+        assert_eq!(error("text"), (1, 1, String::from("unexpected character 't' outside of tags")));
+    }
+}
@@ -0,0 +1,12 @@
+//! Plan model, parsers and linter of Hephaestus
+//!
+//! Both the server and the client use this crate, so plan files are read and checked on the same way
+//! everywhere, e.g.: the client can validate a plan file locally, without server.
+
+pub mod structs;
+
+pub mod lexer;
+pub mod parser;
+pub mod legacy;
+pub mod structured;
+pub mod lint;
@@ -0,0 +1,207 @@
+//! Linter of plan files
+//!
+//! Besides every error of the parser, it reports the problems which would break the plan only at execution:
+//! steps which can never run, work directories which do not exist and users which are unknown on this host.
+//! Every step which runs can end with OK or with failure, so a step can never run only if it requires
+//! such outcomes of its ancestors which exclude each other, e.g.: it depends on a step and on its recovery step.
+//! Work directories and users which contain parameters are not checked, because their value is known only
+//! at execution.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::path::Path;
+
+use crate::structs::enums::{JoinMode, StepStatus, StepType};
+use crate::structs::plan::Plan;
+use crate::structs::step::Step;
+
+use super::parser::{check_plan, file_name, read_plan_file};
+
+/// Check a plan file, return with every problem of it
+pub fn lint_file(path: &Path) -> Vec<String> {
+    match read_plan_file(path) {
+        Ok(content) => lint(&content, &file_name(path)),
+        Err(e) => vec![String::from(e.trim_end())],
+    }
+}
+
+/// Check the content of a plan file, file name is used only in the messages
+pub fn lint(content: &str, file_name: &str) -> Vec<String> {
+    let (plan, mut problems) = check_plan(content, file_name);
+
+    let plan = match plan {
+        Some(plan) => plan,
+        None => return problems,
+    };
+
+    let order = plan.topological_order();
+
+    for (index, step) in plan.steps.iter().enumerate() {
+        if !can_run(&plan, &order, index) {
+            match step.step_type {
+                StepType::Recovery => problems.push(format!("{}: recovery step {} can never run, none of its parents can fail", file_name, step.step_name)),
+                _ => problems.push(format!("{}: step {} can never run, its parents can never end as {:?} join requires", file_name, step.step_name, step.join)),
+            }
+        }
+
+        if let Some(cwd) = step.action.as_ref().and_then(|action| action.cwd.as_ref()) {
+            if !cwd.contains("{{") && !Path::new(cwd).is_dir() {
+                problems.push(format!("{}: step {}: work directory does not exist: {}", file_name, step.step_name, cwd));
+            }
+        }
+
+        if let Some(user) = &step.user {
+            if !user.contains("{{") && !user_exists(user) {
+                problems.push(format!("{}: step {}: user does not exist: {}", file_name, step.step_name, user));
+            }
+        }
+    }
+
+    problems
+}
+
+/// Most ancestors of a step, whose every outcome is tried to find out that the step can run
+const MAX_ANCESTORS: usize = 16;
+
+/// Check that step can run at all
+///
+/// Every combination of OK and failure is tried for the ancestors of step, and statuses are decided on the same way
+/// like at execution. If step has more ancestors than `MAX_ANCESTORS`, it is not checked and it is taken as reachable.
+fn can_run(plan: &Plan, order: &[usize], index: usize) -> bool {
+    let ancestors = ancestors(plan, order, index);
+    if ancestors.len() > MAX_ANCESTORS {
+        return true;
+    }
+
+    for outcomes in 0..(1u32 << ancestors.len()) {
+        let mut statuses: HashMap<&str, StepStatus> = HashMap::new();
+
+        for (bit, ancestor) in ancestors.iter().enumerate() {
+            let step = &plan.steps[*ancestor];
+            let status = match runs(step, &statuses) {
+                true if outcomes & (1 << bit) == 0 => StepStatus::Ok,
+                true => StepStatus::Nok,
+                false => StepStatus::NotRun,
+            };
+            statuses.insert(&step.step_name, status);
+        }
+
+        if runs(&plan.steps[index], &statuses) {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Indexes of the direct and indirect parents of step, in topological order
+fn ancestors(plan: &Plan, order: &[usize], index: usize) -> Vec<usize> {
+    let mut found: HashSet<usize> = HashSet::new();
+    let mut queue: Vec<usize> = vec![index];
+
+    while let Some(current) = queue.pop() {
+        for parent in &plan.steps[current].parents {
+            if let Some(parent) = plan.steps.iter().position(|s| &s.step_name == parent) {
+                if found.insert(parent) {
+                    queue.push(parent);
+                }
+            }
+        }
+    }
+
+    order.iter().filter(|index| found.contains(index)).cloned().collect()
+}
+
+/// Decide that step runs when all of its parents have ended, by the same rules like at execution
+///
+/// Recovery step runs if any parent has failed, finally step runs whatever happened, the other steps are decided by their join mode.
+fn runs(step: &Step, statuses: &HashMap<&str, StepStatus>) -> bool {
+    let mut parents = step.parents.iter().map(|parent| statuses.get(&parent[..]).unwrap_or(&StepStatus::NotRun));
+
+    if step.step_type == StepType::Finally || step.parents.is_empty() {
+        return true;
+    }
+
+    if step.step_type == StepType::Recovery {
+        return parents.any(|status| *status == StepStatus::Nok);
+    }
+
+    match step.join {
+        JoinMode::AllOk => parents.all(|status| *status == StepStatus::Ok),
+        JoinMode::AnyOk => parents.any(|status| *status == StepStatus::Ok),
+        JoinMode::AllDone => true,
+    }
+}
+
+/// Check that user exists on this host
+fn user_exists(name: &str) -> bool {
+    let name = match CString::new(name) {
+        Ok(name) => name,
+        Err(_) => return false,
+    };
+
+    unsafe { !libc::getpwnam(name.as_ptr()).is_null() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Problems of plan without the checks of work directories and users
+    fn problems(steps: &str) -> Vec<String> {
+        lint(&format!("<plan id=\"test\"/>\n{}", steps), "test.conf")
+    }
+
+    #[test]
+    fn orphan_recovery_step() {
+        // Check can run only if step01 is OK, but fix01 only if it has failed, so verify can never run and neither can its recovery
+        let content = r#"
+<step name="step01" desc="d">echo</step>
+<step name="check" desc="d" parent="step01">echo</step>
+<recovery name="fix01" desc="d" parent="step01">echo</recovery>
+<step name="verify" desc="d" parent="check,fix01">echo</step>
+<recovery name="fix02" desc="d" parent="verify">echo</recovery>
+"#;
+
+        assert_eq!(problems(content), vec![
+            "test.conf: step verify can never run, its parents can never end as all_ok join requires",
+            "test.conf: recovery step fix02 can never run, none of its parents can fail",
+        ]);
+    }
+
+    #[test]
+    fn step_behind_skipped_branches() {
+        // Deploy requires that build is both OK and failed through its branches
+        let content = r#"
+<step name="build" desc="d">echo</step>
+<step name="test" desc="d" parent="build">echo</step>
+<recovery name="report" desc="d" parent="build">echo</recovery>
+<step name="deploy" desc="d" parent="test" parent="report" join="JOIN">echo</step>
+<step name="notify" desc="d" parent="deploy">echo</step>
+<step name="log" desc="d" parent="deploy" join="all_done">echo</step>
+"#;
+
+        assert_eq!(problems(&content.replace("JOIN", "all_ok")), vec![
+            "test.conf: step deploy can never run, its parents can never end as all_ok join requires",
+            "test.conf: step notify can never run, its parents can never end as all_ok join requires",
+        ]);
+        assert!(problems(&content.replace("JOIN", "any_ok")).is_empty());
+    }
+
+    #[test]
+    fn reachable_steps() {
+        // Every kind of step can fail, so recovery of recovery, finally or approval step can run
+        let content = r#"
+<step name="step01" desc="d">echo</step>
+<recovery name="fix01" desc="d" parent="step01">echo</recovery>
+<recovery name="fix02" desc="d" parent="fix01">echo</recovery>
+<finally name="clean" desc="d" parent="step01">echo</finally>
+<recovery name="fix03" desc="d" parent="clean">echo</recovery>
+<approval name="approve" desc="d" parent="step01"/>
+<recovery name="rejected" desc="d" parent="approve">echo</recovery>
+<step name="last" desc="d" parent="fix02,fix03" join="all_done">echo</step>
+"#;
+
+        assert!(problems(content).is_empty(), "{:?}", problems(content));
+    }
+}
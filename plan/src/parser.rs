@@ -0,0 +1,758 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::structs::plan::Plan;
+use crate::structs::step::{Step, MAX_RETRY, MAX_RETRY_BACKOFF, MAX_RETRY_DELAY};
+use crate::structs::action::Action;
+use crate::structs::enums::{Concurrency, JoinMode, Overlap, ParamType, StepType};
+use crate::structs::param::PlanParam;
+use crate::structs::schedule::{CronExpr, PlanSchedule};
+
+use super::legacy::{is_legacy, parse_legacy};
+use super::lexer::{Lexer, Location, ParseError, Token};
+use super::structured::{parse_toml, parse_yaml};
+
+/// Tags whose content is not tokenized but kept as it is
+const RAW_TAGS: [&str; 3] = ["step", "recovery", "finally"];
+
+/// Attributes which can be used for approval steps
+const APPROVAL_ATTRS: [&str; 5] = ["name", "desc", "parent", "join", "timeout"];
+
+/// Attributes which can be specified more times within one tag
+pub const REPEATABLE_ATTRS: [&str; 2] = ["setenv", "parent"];
+
+/// Attribute of a tag or option of a step: name, value and location of value
+pub type Attribute = (String, String, Location);
+
+/// Reference of a step to its parent: index of step, name of parent and location of reference
+pub type ParentRef = (usize, String, Location);
+
+/// Extensions of plan files: `conf` is the tag or the legacy format, the others are TOML and YAML
+pub const PLAN_EXTENSIONS: [&str; 3] = ["conf", "toml", "yaml"];
+
+/// Read the plan file and create a vector from its steps
+/// 
+/// This is an internal function in this module. It read and collect information about specified config file.
+/// Errors are reported with their location, for example: `test2.conf:12:7: unknown attribute 'parnet'`.
+pub fn collect_steps(path: &Path) -> Result<Plan, String> {
+    let content = read_plan_file(path)?;
+    parse_plan(&content, &file_name(path))
+}
+
+/// Read the content of plan file as it is, e.g.: to keep a snapshot about it
+pub fn read_plan_file(path: &Path) -> Result<String, String> {
+    match fs::read_to_string(path) {
+        Ok(c) => Ok(c),
+        Err(e) => Err(format!("Error during open '{}': {:?}\n", path.display(), e)),
+    }
+}
+
+/// Check that the file is a plan file by its extension
+pub fn is_plan_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| PLAN_EXTENSIONS.iter().any(|e| ext == *e))
+}
+
+/// Find the file of plan in the directory of plan set, whichever format it is written in
+///
+/// If plan does not exist, the path of tag format is returned, so it is reported like before when it is read.
+/// Plan cannot be defined in more files, because it would not be clear which one has to be executed.
+pub fn find_plan_file(set_dir: &Path, plan: &str) -> Result<PathBuf, String> {
+    let mut found: Vec<PathBuf> = PLAN_EXTENSIONS.iter()
+        .map(|ext| set_dir.join(format!("{}.{}", plan, ext)))
+        .filter(|path| path.is_file())
+        .collect();
+
+    match found.len() {
+        0 => Ok(set_dir.join(format!("{}.conf", plan))),
+        1 => Ok(found.remove(0)),
+        _ => {
+            let names: Vec<String> = found.iter().map(|path| file_name(path)).collect();
+            Err(format!("Plan is defined in more files: {}", names.join(", ")))
+        },
+    }
+}
+
+/// Name of the plan file, which is used in the error messages
+pub fn file_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => format!("{}", path.display()),
+    }
+}
+
+/// Parse the content of a plan file, file name is used only for error messages
+///
+/// If plan has more errors, every one of them is reported in a separate line.
+pub fn parse_plan(content: &str, file_name: &str) -> Result<Plan, String> {
+    match check_plan(content, file_name) {
+        (Some(plan), errors) if errors.is_empty() => Ok(plan),
+        (_, errors) => Err(errors.join("\n")),
+    }
+}
+
+/// Parse the content of a plan file and collect every error, instead of stopping at the first one
+///
+/// Plan is returned even if it has errors, so it can be checked further, e.g. by the linter.
+/// Syntax errors stop the parsing, because the rest of the file cannot be interpreted after them.
+/// TOML and YAML files are recognized by their extension. Other files in the legacy key-value format are
+/// recognized by their content and parsed by the legacy parser.
+pub fn check_plan(content: &str, file_name: &str) -> (Option<Plan>, Vec<String>) {
+    let mut errors: Vec<ParseError> = Vec::new();
+
+    let plan = match Path::new(file_name).extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => parse_toml(content, &mut errors),
+        Some("yaml") => parse_yaml(content, &mut errors),
+        _ if is_legacy(content) => Some(parse_legacy(content, file_name, &mut errors)),
+        _ => match parse_tokens(&mut Lexer::new(content, &RAW_TAGS), &mut errors) {
+            Ok(plan) => plan,
+            Err(e) => {
+                errors.push(e);
+                None
+            }
+        },
+    };
+
+    errors.sort_by_key(|e| (e.location.line, e.location.col));
+
+    let messages = errors.iter()
+        .map(|e| match e.location.line {
+            0 => format!("{}: {}", file_name, e.message),
+            line => format!("{}:{}:{}: {}", file_name, line, e.location.col, e.message),
+        })
+        .collect();
+
+    (plan, messages)
+}
+
+/// Process the tokens of file: one plan tag and any number of step, recovery, finally and approval tags
+///
+/// Errors in the content of tags are collected into `errors`, only syntax errors are returned.
+fn parse_tokens(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<Option<Plan>, ParseError> {
+    let mut plan: Option<Plan> = None;
+    let mut steps: Vec<Step> = Vec::new();
+    let mut step_locations: Vec<Location> = Vec::new();
+    let mut parent_refs: Vec<ParentRef> = Vec::new();
+
+    loop {
+        let (token, location) = lexer.next_token()?;
+
+        match token {
+            Token::Eof => break,
+            Token::Open(tag) if tag == "plan" => {
+                let parsed = parse_plan_tag(lexer, location, errors)?;
+                if plan.is_some() {
+                    errors.push(ParseError::new(location, String::from("plan tag is defined more times")));
+                    continue;
+                }
+                plan = Some(parsed);
+            },
+            Token::Open(tag) if RAW_TAGS.contains(&&tag[..]) || tag == "approval" => {
+                let (step, parents) = parse_step_tag(lexer, &tag, location, steps.len(), errors)?;
+                steps.push(step);
+                step_locations.push(location);
+                parent_refs.extend(parents);
+            },
+            Token::Open(tag) => return Err(ParseError::new(location, format!("unknown tag '<{}>'", tag))),
+            other => return Err(ParseError::new(location, format!("unexpected {}", other))),
+        }
+    }
+
+    let mut plan = match plan {
+        Some(plan) => plan,
+        None => {
+            errors.push(ParseError::new(Location { line: 1, col: 1 }, String::from("Plan ID is missing")));
+            return Ok(None);
+        }
+    };
+    plan.steps = steps;
+
+    check_graph(&plan, &step_locations, &parent_refs, errors);
+
+    Ok(Some(plan))
+}
+
+/// Check the steps as a graph, when every step is known: names must be unique, parents must exist and there cannot be cycle
+///
+/// Steps can be defined in any order, parent can be after its dependants in the file.
+pub fn check_graph(plan: &Plan, step_locations: &[Location], parent_refs: &[ParentRef], errors: &mut Vec<ParseError>) {
+    for (index, step) in plan.steps.iter().enumerate() {
+        if plan.steps[..index].iter().any(|s| s.step_name == step.step_name) {
+            errors.push(ParseError::new(step_locations[index], format!("step name is used more times: {}", step.step_name)));
+        }
+    }
+
+    for (index, parent, location) in parent_refs {
+        let step = &plan.steps[*index];
+
+        match plan.steps.iter().find(|s| &s.step_name == parent) {
+            None => errors.push(ParseError::new(*location, format!("parent of {} does not exist: {}", step.step_name, parent))),
+            // Finally step without parent runs at the end of plan, so only an other finally step can wait for it
+            Some(parent_step) if parent_step.step_type == StepType::Finally && parent_step.parents.is_empty() && step.step_type != StepType::Finally => {
+                errors.push(ParseError::new(*location, format!("{} runs at the end of plan, only finally step can depend from it", parent)));
+            },
+            Some(_) => (),
+        }
+    }
+
+    let order = plan.topological_order();
+    if order.len() == plan.steps.len() {
+        return;
+    }
+
+    // Steps which are not in the order are in a cycle or depend on one, every cycle is reported once
+    let mut reported: Vec<usize> = order;
+
+    for start in 0..plan.steps.len() {
+        let mut path: Vec<usize> = Vec::new();
+        let mut current = start;
+        let mut cycle_start: Option<usize> = None;
+
+        // Walk through the parents which are not in the order, until a step is visited again
+        while !reported.contains(&current) {
+            if let Some(position) = path.iter().position(|index| *index == current) {
+                cycle_start = Some(position);
+                break;
+            }
+            path.push(current);
+
+            let parent = plan.steps[current].parents.iter()
+                .filter_map(|parent| plan.steps.iter().position(|s| &s.step_name == parent))
+                .find(|parent| !reported.contains(parent));
+
+            current = match parent {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+
+        if let Some(position) = cycle_start {
+            let cycle: Vec<usize> = path[position..].iter().rev().cloned().collect();
+            let mut names: Vec<&str> = cycle.iter().map(|index| &plan.steps[*index].step_name[..]).collect();
+            names.push(names[0]);
+
+            let first = *cycle.iter().min().unwrap_or(&start);
+            errors.push(ParseError::new(step_locations[first], format!("steps depend on each other: {}", names.join(" -> "))));
+        }
+
+        reported.extend(path);
+    }
+}
+
+/// Read attributes of an open tag, return with them and with the info that tag is self closed
+fn read_attributes(lexer: &mut Lexer, errors: &mut Vec<ParseError>) -> Result<(Vec<Attribute>, bool), ParseError> {
+    let mut attrs: Vec<Attribute> = Vec::new();
+
+    loop {
+        let (token, location) = lexer.next_token()?;
+
+        match token {
+            Token::Attr(name, value) => {
+                if !REPEATABLE_ATTRS.contains(&&name[..]) && attrs.iter().any(|a| a.0 == name) {
+                    errors.push(ParseError::new(location, format!("attribute '{}' is specified more times", name)));
+                    continue;
+                }
+                attrs.push((name, value, location));
+            },
+            Token::TagEnd => return Ok((attrs, false)),
+            Token::SelfClose => return Ok((attrs, true)),
+            other => return Err(ParseError::new(location, format!("unexpected {} in tag", other))),
+        }
+    }
+}
+
+/// Expect that the next token is the specified close tag
+fn expect_close(lexer: &mut Lexer, tag: &str) -> Result<(), ParseError> {
+    let (token, location) = lexer.next_token()?;
+    match token {
+        Token::Close(name) if name == tag => Ok(()),
+        other => Err(ParseError::new(location, format!("'</{}>' is expected, but found {}", tag, other))),
+    }
+}
+
+/// Process plan tag with its parameters
+fn parse_plan_tag(lexer: &mut Lexer, location: Location, errors: &mut Vec<ParseError>) -> Result<Plan, ParseError> {
+    let (attrs, self_closed) = read_attributes(lexer, errors)?;
+    let (mut plan, schedule_location) = plan_attributes(attrs, location, errors);
+
+    if self_closed {
+        return Ok(plan);
+    }
+
+    let mut params: Vec<(PlanParam, Location)> = Vec::new();
+
+    loop {
+        let (token, location) = lexer.next_token()?;
+
+        match token {
+            Token::Close(tag) if tag == "plan" => break,
+            Token::Open(tag) if tag == "param" => params.push((parse_param_tag(lexer, location, errors)?, location)),
+            Token::Open(tag) => return Err(ParseError::new(location, format!("unknown tag '<{}>' within plan", tag))),
+            other => return Err(ParseError::new(location, format!("'</plan>' is expected, but found {}", other))),
+        }
+    }
+
+    add_params(&mut plan, params, schedule_location, errors);
+
+    Ok(plan)
+}
+
+/// Process the attributes of plan, it is used by every plan format which has them
+///
+/// Location of schedule is returned too, because parameters of scheduled plan are checked against it.
+pub fn plan_attributes(attrs: Vec<Attribute>, location: Location, errors: &mut Vec<ParseError>) -> (Plan, Location) {
+    let mut plan = Plan::new(String::new(), Vec::new());
+
+    let mut schedule: Option<(PlanSchedule, Location)> = None;
+    let mut timezone: Option<(Tz, Location)> = None;
+    let mut overlap: Option<(Overlap, Location)> = None;
+    let mut concurrency: Option<(Concurrency, Location)> = None;
+    let mut schedule_location = location;
+
+    for (name, value, location) in attrs {
+        match &name[..] {
+            "id" => plan.id = value,
+            "max_parallel" => match value.parse::<usize>() {
+                Ok(limit) => plan.max_parallel = Some(limit),
+                Err(_) => errors.push(ParseError::new(location, format!("parallel limit must be a number: {}", value))),
+            },
+            "timeout" => match parse_duration(&value) {
+                Ok(timeout) => plan.timeout = Some(timeout),
+                Err(e) => errors.push(ParseError::new(location, e)),
+            },
+            "schedule" => match CronExpr::parse(&value) {
+                Ok(cron) => schedule = Some((PlanSchedule::new(value, cron), location)),
+                Err(e) => errors.push(ParseError::new(location, e)),
+            },
+            "timezone" => match value.parse::<Tz>() {
+                Ok(tz) => timezone = Some((tz, location)),
+                Err(_) => errors.push(ParseError::new(location, format!("unknown timezone: {}", value))),
+            },
+            "overlap" => match &value[..] {
+                "skip" => overlap = Some((Overlap::Skip, location)),
+                "queue" => overlap = Some((Overlap::Queue, location)),
+                "allow" => overlap = Some((Overlap::Allow, location)),
+                _ => errors.push(ParseError::new(location, format!("overlap must be skip, queue or allow: {}", value))),
+            },
+            "concurrency" => match &value[..] {
+                "single" => concurrency = Some((Concurrency::Single, location)),
+                "queue" => concurrency = Some((Concurrency::Queue, location)),
+                "allow" => concurrency = Some((Concurrency::Allow, location)),
+                _ => errors.push(ParseError::new(location, format!("concurrency must be single, queue or allow: {}", value))),
+            },
+            "lock" => {
+                if value.trim().is_empty() {
+                    errors.push(ParseError::new(location, String::from("lock name cannot be empty")));
+                    continue;
+                }
+                plan.lock = Some(value);
+            },
+            _ => errors.push(ParseError::new(location, format!("unknown attribute '{}'", name))),
+        }
+    }
+
+    if plan.id.is_empty() {
+        errors.push(ParseError::new(location, String::from("Plan ID is missing")));
+    }
+
+    // Lock makes sense only if runs are restricted, by default they are queued
+    plan.concurrency = match (concurrency, &plan.lock) {
+        (Some((Concurrency::Allow, location)), Some(_)) => {
+            errors.push(ParseError::new(location, String::from("concurrency cannot be allow if lock is specified")));
+            Concurrency::Queue
+        },
+        (Some((concurrency, _)), _) => concurrency,
+        (None, Some(_)) => Concurrency::Queue,
+        (None, None) => Concurrency::Allow,
+    };
+
+    // Timezone and overlap belong to the schedule, they are meaningless without it
+    match schedule {
+        Some((mut sched, location)) => {
+            sched.timezone = timezone.map(|tz| tz.0);
+            if let Some((overlap, _)) = overlap {
+                sched.overlap = overlap;
+            }
+            plan.schedule = Some(sched);
+            schedule_location = location;
+        },
+        None => {
+            if let Some((_, location)) = timezone {
+                errors.push(ParseError::new(location, String::from("timezone can be specified only with schedule")));
+            }
+            if let Some((_, location)) = overlap {
+                errors.push(ParseError::new(location, String::from("overlap can be specified only with schedule")));
+            }
+        },
+    }
+
+    (plan, schedule_location)
+}
+
+/// Add the parameters to the plan, names must be unique and scheduled plan cannot have mandatory parameter
+pub fn add_params(plan: &mut Plan, params: Vec<(PlanParam, Location)>, schedule_location: Location, errors: &mut Vec<ParseError>) {
+    for (param, location) in params {
+        if plan.params.iter().any(|p| p.name == param.name) {
+            errors.push(ParseError::new(location, format!("parameter is defined more times: {}", param.name)));
+            continue;
+        }
+        plan.params.push(param);
+    }
+
+    // Scheduler can start the plan only with default parameter values
+    if plan.schedule.is_some() {
+        if let Some(param) = plan.params.iter().find(|p| p.default.is_none()) {
+            errors.push(ParseError::new(schedule_location, format!("scheduled plan cannot have mandatory parameter: {}", param.name)));
+        }
+    }
+}
+
+/// Process a parameter tag of plan
+fn parse_param_tag(lexer: &mut Lexer, location: Location, errors: &mut Vec<ParseError>) -> Result<PlanParam, ParseError> {
+    let (attrs, self_closed) = read_attributes(lexer, errors)?;
+
+    if !self_closed {
+        expect_close(lexer, "param")?;
+    }
+
+    Ok(param_attributes(attrs, location, errors))
+}
+
+/// Process the attributes of a parameter, it is used by every plan format which has them
+pub fn param_attributes(attrs: Vec<Attribute>, location: Location, errors: &mut Vec<ParseError>) -> PlanParam {
+    let mut param = PlanParam::new(String::new());
+
+    for (name, value, location) in attrs {
+        match &name[..] {
+            "name" => param.name = value,
+            "type" => match &value[..] {
+                "string" => param.param_type = ParamType::String,
+                "int" => param.param_type = ParamType::Int,
+                "bool" => param.param_type = ParamType::Bool,
+                _ => errors.push(ParseError::new(location, format!("parameter type must be string, int or bool: {}", value))),
+            },
+            "default" => param.default = Some(value),
+            _ => errors.push(ParseError::new(location, format!("unknown attribute '{}'", name))),
+        }
+    }
+
+    if param.name.is_empty() {
+        errors.push(ParseError::new(location, String::from("parameter name cannot be empty")));
+    }
+    else if param.name.starts_with(|c: char| c.is_ascii_digit()) || !param.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        // Name is part of a shell variable name in the command
+        errors.push(ParseError::new(location, format!("parameter name can contain only letters, digits and underscore, and cannot begin with digit: {}", param.name)));
+    }
+
+    if let Some(default) = &param.default {
+        if let Err(e) = param.check_value(default) {
+            errors.push(ParseError::new(location, e));
+        }
+    }
+
+    param
+}
+
+/// Process a step, recovery, finally or approval tag with its command
+///
+/// Whole tag is read first, so parsing can be continued with the next tag even if this one has errors.
+/// Parents are returned with their location too, because they can be checked only when every step is known.
+fn parse_step_tag(lexer: &mut Lexer, tag: &str, location: Location, index: usize, errors: &mut Vec<ParseError>) -> Result<(Step, Vec<ParentRef>), ParseError> {
+    let (attrs, self_closed) = read_attributes(lexer, errors)?;
+
+    let mut step: Step = Step::new_empty();
+    step.step_type = match tag {
+        "recovery" => StepType::Recovery,
+        "finally" => StepType::Finally,
+        "approval" => StepType::Approval,
+        _ => StepType::Action,
+    };
+
+    let mut body: Option<String> = None;
+
+    if !self_closed && step.step_type == StepType::Approval {
+        expect_close(lexer, tag)?;
+    }
+    else if !self_closed {
+        let (token, text_location) = lexer.next_token()?;
+        let text = match token {
+            Token::Text(text) => text,
+            other => return Err(ParseError::new(text_location, format!("command is expected, but found {}", other))),
+        };
+        expect_close(lexer, tag)?;
+
+        // Leading and trailing empty lines are just formatting of the tags
+        let mut lines: Vec<&str> = text.split('\n').map(|l| l.trim_end_matches('\r')).collect();
+        while lines.last().is_some_and(|l| l.trim().is_empty()) {
+            lines.pop();
+        }
+        while lines.first().is_some_and(|l| l.trim().is_empty()) {
+            lines.remove(0);
+        }
+
+        if !lines.is_empty() {
+            body = Some(lines.join("\n"));
+        }
+    }
+
+    let mut cwd: Option<String> = None;
+    let mut parents: Vec<ParentRef> = Vec::new();
+
+    for (name, value, location) in attrs {
+        // Parents are checked later, when every step is known
+        let known_parents = step.parents.len();
+        step_attribute(&mut step, &name, value, location, &mut cwd, errors);
+
+        for parent in &step.parents[known_parents..] {
+            parents.push((index, parent.clone(), location));
+        }
+    }
+
+    if let Some(body) = body {
+        step.action = Some(Action::new(body, cwd));
+    }
+
+    if let Err(e) = step.validate() {
+        for line in e.lines() {
+            errors.push(ParseError::new(location, String::from(line)));
+        }
+    }
+
+    Ok((step, parents))
+}
+
+/// Process an attribute of step, it is used by every plan format
+///
+/// Parents are added to the step, but it is not checked that they exist.
+pub fn step_attribute(step: &mut Step, name: &str, value: String, location: Location, cwd: &mut Option<String>, errors: &mut Vec<ParseError>) {
+    // Approval has no command, so options of command cannot be used
+    if step.step_type == StepType::Approval && !APPROVAL_ATTRS.contains(&name) {
+        errors.push(ParseError::new(location, format!("attribute '{}' cannot be used for approval", name)));
+        return;
+    }
+
+    match name {
+        "name" => step.step_name = value,
+        "desc" => step.description = value,
+        "user" => step.user = Some(value),
+        "cwd" => *cwd = Some(value),
+        "parent" => {
+            // More parents can be listed separated by comma, or parent can be specified more times
+            for parent in value.split(',').map(|p| p.trim()) {
+                if parent.is_empty() {
+                    errors.push(ParseError::new(location, format!("parent name cannot be empty: '{}'", value)));
+                    continue;
+                }
+
+                if step.parents.iter().any(|p| p == parent) {
+                    errors.push(ParseError::new(location, format!("parent is specified more times: {}", parent)));
+                    continue;
+                }
+
+                step.parents.push(String::from(parent));
+            }
+        },
+        "join" => match &value[..] {
+            "all_ok" => step.join = JoinMode::AllOk,
+            "any_ok" => step.join = JoinMode::AnyOk,
+            "all_done" => step.join = JoinMode::AllDone,
+            _ => errors.push(ParseError::new(location, format!("join must be all_ok, any_ok or all_done: {}", value))),
+        },
+        "setenv" => {
+            let (key, value) = match value.trim().split_once(char::is_whitespace) {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (value.trim(), ""),
+            };
+            if key.is_empty() || value.is_empty() {
+                errors.push(ParseError::new(location, String::from("key and/or value is missing in setenv option")));
+                return;
+            }
+            step.envvars.insert(String::from(key), String::from(value));
+        },
+        "timeout" => match parse_duration(&value) {
+            Ok(timeout) => step.timeout = Some(timeout),
+            Err(e) => errors.push(ParseError::new(location, e)),
+        },
+        "retry" => match value.parse::<u32>() {
+            Ok(retry) if retry <= MAX_RETRY => step.retry = retry,
+            _ => errors.push(ParseError::new(location, format!("retry must be a number between 0 and {}: {}", MAX_RETRY, value))),
+        },
+        "retry_delay" => match parse_duration(&value) {
+            Ok(delay) if delay <= MAX_RETRY_DELAY => step.retry_delay = delay,
+            Ok(_) => errors.push(ParseError::new(location, format!("retry delay cannot be longer than {}h: {}", MAX_RETRY_DELAY.as_secs() / 3600, value))),
+            Err(e) => errors.push(ParseError::new(location, e)),
+        },
+        "retry_backoff" => match value.parse::<u32>() {
+            Ok(backoff) if backoff > 0 && backoff <= MAX_RETRY_BACKOFF => step.retry_backoff = backoff,
+            _ => errors.push(ParseError::new(location, format!("retry backoff must be a number between 1 and {}: {}", MAX_RETRY_BACKOFF, value))),
+        },
+        _ => errors.push(ParseError::new(location, format!("unknown attribute '{}'", name))),
+    }
+}
+
+/// Longest duration which can be specified, longer ones are surely mistakes and they would overflow at execution
+const MAX_DURATION: u64 = 365 * 24 * 60 * 60;
+
+/// Parse time duration like "90s", "30m", "1h30m" or "2d"
+///
+/// Number without unit is taken as seconds. Duration cannot be longer than 365 days.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(String::from("Duration cannot be empty"));
+    }
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+
+        let multiplier = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("Invalid unit '{}' in duration: {}", c, text)),
+        };
+
+        let value = match number.parse::<u64>() {
+            Ok(v) => v,
+            Err(_) if number.is_empty() => return Err(format!("Number is missing before '{}' in duration: {}", c, text)),
+            Err(_) => return Err(format!("Duration is too long: {}", text)),
+        };
+
+        seconds = match value.checked_mul(multiplier).and_then(|value| seconds.checked_add(value)) {
+            Some(seconds) => seconds,
+            None => return Err(format!("Duration is too long: {}", text)),
+        };
+        number = String::new();
+    }
+
+    if !number.is_empty() {
+        // Only digits are collected, so number can be wrong only if it is too big
+        seconds = match number.parse::<u64>().ok().and_then(|value| seconds.checked_add(value)) {
+            Some(seconds) => seconds,
+            None => return Err(format!("Duration is too long: {}", text)),
+        };
+    }
+
+    if seconds > MAX_DURATION {
+        return Err(format!("Duration cannot be longer than 365d: {}", text));
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Every property of plan in text, so plans which are parsed from different formats can be compared
+    pub(crate) fn describe_plan(plan: &Plan) -> Vec<String> {
+        let mut lines = vec![format!(
+            "plan {} max_parallel={:?} timeout={:?} concurrency={:?} lock={:?} schedule={:?}",
+            plan.id,
+            plan.max_parallel,
+            plan.timeout,
+            plan.concurrency,
+            plan.lock,
+            plan.schedule.as_ref().map(|s| (s.expression.clone(), s.timezone_name(), s.overlap)),
+        )];
+
+        for param in &plan.params {
+            lines.push(format!("param {} {:?} default={:?}", param.name, param.param_type, param.default));
+        }
+
+        for step in &plan.steps {
+            let env: std::collections::BTreeMap<_, _> = step.envvars.iter().collect();
+            lines.push(format!(
+                "step {} {:?} desc={:?} parents={:?} join={:?} user={:?} action={:?} env={:?} timeout={:?} retry={} delay={:?} backoff={}",
+                step.step_name,
+                step.step_type,
+                step.description,
+                step.parents,
+                step.join,
+                step.user,
+                step.action,
+                env,
+                step.timeout,
+                step.retry,
+                step.retry_delay,
+                step.retry_backoff,
+            ));
+        }
+
+        lines
+    }
+
+    /// Every problem of the plan, file name is used only in the messages and to decide the format
+    pub(crate) fn errors(content: &str, file_name: &str) -> Vec<String> {
+        let (_, errors) = check_plan(content, file_name);
+        errors
+    }
+
+    /// Check that the plan is parsed to the same like the plan in tag format
+    pub(crate) fn assert_same_plan(content: &str, file_name: &str, tag: &str) {
+        let plan = parse_plan(content, file_name).unwrap();
+        let tag = parse_plan(tag, "test.conf").unwrap();
+
+        assert_eq!(describe_plan(&plan), describe_plan(&tag), "{} differs from tag format", file_name);
+    }
+
+    #[test]
+    fn parse_steps() {
+        let content = "<plan id=\"test\" max_parallel=\"2\"/>\n\n<step name=\"s1\" desc=\"first\" setenv=\"A 1\">\n\n  echo $A\n\n</step>\n<finally name=\"s2\" desc=\"cleanup\" parent=\"s1\">rm -f x</finally>\n";
+        let plan = parse_plan(content, "test.conf").unwrap();
+
+        assert_eq!(plan.id, "test");
+        assert_eq!(plan.max_parallel, Some(2));
+        assert_eq!(plan.steps.len(), 2);
+        assert_eq!(plan.steps[0].step_name, "s1");
+        assert_eq!(plan.steps[0].description, "first");
+        assert_eq!(plan.steps[0].envvars.get("A").map(|v| &v[..]), Some("1"));
+        assert_eq!(plan.steps[0].action.as_ref().map(|a| &a.cmd[..]), Some("  echo $A"));
+        assert_eq!(plan.steps[1].step_type, StepType::Finally);
+        assert_eq!(plan.steps[1].parents, vec![String::from("s1")]);
+    }
+
+    #[test]
+    fn syntax_error_has_location() {
+        assert_eq!(errors("<plan id=\"test\"/>\n<step name=\"s1\">\n  echo\n", "test.conf"), vec!["test.conf:2:17: '</step>' is missing"]);
+        assert_eq!(errors("<plan id=\"test\"/>\n\n   <stpe name=\"s1\"/>", "test.conf"), vec!["test.conf:3:4: unknown tag '<stpe>'"]);
+    }
+
+    #[test]
+    fn every_error_is_reported_in_order() {
+        let content = "<plan id=\"test\" timeout=\"5x\"/>\n<step name=\"s1\" desc=\"d\" parnet=\"s0\">echo</step>\n<step name=\"s2\" desc=\"d\" parent=\"s3\" retry=\"x\">echo</step>\n";
+
+        assert_eq!(errors(content, "test.conf"), vec![
+            "test.conf:1:17: Invalid unit 'x' in duration: 5x",
+            "test.conf:2:26: unknown attribute 'parnet'",
+            "test.conf:3:26: parent of s2 does not exist: s3",
+            "test.conf:3:38: retry must be a number between 0 and 100: x",
+        ]);
+    }
+
+    #[test]
+    fn duplicated_names_and_cycles() {
+        let content = "<plan id=\"test\"/>\n<step name=\"a\" desc=\"d\" parent=\"b\">echo</step>\n<step name=\"b\" desc=\"d\" parent=\"a\">echo</step>\n<step name=\"a\" desc=\"d\" name=\"c\">echo</step>\n";
+
+        assert_eq!(errors(content, "test.conf"), vec![
+            "test.conf:2:1: steps depend on each other: b -> a -> b",
+            "test.conf:4:1: step name is used more times: a",
+            "test.conf:4:25: attribute 'name' is specified more times",
+        ]);
+    }
+
+    #[test]
+    fn missing_plan_tag() {
+        assert_eq!(errors("<step name=\"s1\" desc=\"d\">echo</step>", "test.conf"), vec!["test.conf:1:1: Plan ID is missing"]);
+    }
+
+}
@@ -1,11 +1,11 @@
 use std::fmt;
 
 /// Action alias command:
-/// - cmd => Command which must be executed
-/// - args => Arguments of program
+/// - cmd => Script which must be executed, as it is written in the plan
+/// - cwd => Work directory of command
 #[derive(Clone)]
 pub struct Action {
-    pub cmd: Vec<String>,
+    pub cmd: String,
     pub cwd: Option<String>,
 }
 
@@ -20,7 +20,7 @@ impl fmt::Debug for Action {
 
 impl fmt::Display for Action {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut full_cmd = self.cmd.join(" ");
+        let mut full_cmd = self.cmd.clone();
         if let Some(cwd) = &self.cwd {
             full_cmd = format!("cd {} && {}", cwd, full_cmd);
         }
@@ -30,10 +30,9 @@ impl fmt::Display for Action {
 
 impl Action {
     pub fn new(cmd: String, cwd: Option<String>) -> Action {
-        let base = vec![String::from(cmd)];
-        return Action {
-            cmd: base,
-            cwd: cwd,
+        Action {
+            cmd,
+            cwd,
         }
     }
 }
\ No newline at end of file
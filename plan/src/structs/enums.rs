@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Type of step:
+/// - Action => Regular step
+/// - Recovery => Regular step has failed, it is a recovery step for regular step
+/// - Finally => Step runs whatever happened with its parents, e.g.: cleanup
+/// - Approval => Step has no command, it waits until someone approves or rejects it
+/// - None => Step type is not set yet
+#[derive(Eq, PartialEq, Clone)]
+pub enum StepType {
+    Action,
+    Recovery,
+    Finally,
+    Approval,
+    None,
+}
+
+impl fmt::Debug for StepType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            StepType::Action => "step",
+            StepType::Recovery => "recovery",
+            StepType::Finally => "finally",
+            StepType::Approval => "approval",
+            StepType::None => "not specified",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for StepType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "step" => Ok(StepType::Action),
+            "recovery" => Ok(StepType::Recovery),
+            "finally" => Ok(StepType::Finally),
+            "approval" => Ok(StepType::Approval),
+            _ => Err(format!("Invalid step type: {}", s)),
+        }
+    }
+}
+
+/// How the statuses of parents are joined, when step has more parents:
+/// - AllOk => Every parent must end with OK
+/// - AnyOk => At least one parent must end with OK
+/// - AllDone => Every parent must be ended, whatever is its status
+#[derive(Eq, PartialEq, Clone, Copy)]
+pub enum JoinMode {
+    AllOk,
+    AnyOk,
+    AllDone,
+}
+
+impl fmt::Debug for JoinMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            JoinMode::AllOk => "all_ok",
+            JoinMode::AnyOk => "any_ok",
+            JoinMode::AllDone => "all_done",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// What happens when a plan is executed while another run of it, or of a plan with the same lock, is running:
+/// - Single => Execution is rejected
+/// - Queue => Run waits until the other runs have ended
+/// - Allow => Runs are not restricted
+#[derive(Eq, PartialEq, Clone, Copy)]
+pub enum Concurrency {
+    Single,
+    Queue,
+    Allow,
+}
+
+impl fmt::Debug for Concurrency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            Concurrency::Single => "single",
+            Concurrency::Queue => "queue",
+            Concurrency::Allow => "allow",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// What happens when a scheduled plan should be started, but its previous run is still running:
+/// - Skip => This run is skipped
+/// - Queue => Run is started when the previous one has ended, more queued runs are merged into one
+/// - Allow => Run is started parallel with the previous one
+#[derive(Eq, PartialEq, Clone, Copy)]
+pub enum Overlap {
+    Skip,
+    Queue,
+    Allow,
+}
+
+impl fmt::Debug for Overlap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            Overlap::Skip => "skip",
+            Overlap::Queue => "queue",
+            Overlap::Allow => "allow",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+/// Enum for step running status:
+/// - Ok => Command has run with 0 code
+/// - Nok => Command has run with higher than 0 code
+/// - Failed => Some internal issue happened
+/// - NotRun => Step is waiting for execution
+/// - TimedOut => Command did not finish in time, so it was killed
+/// - Cancelled => Plan was cancelled before or during the step
+#[derive(Eq, PartialEq, Clone)]
+pub enum StepStatus {
+    Ok,
+    Nok,
+    NotRun,
+    Failed,
+    TimedOut,
+    Cancelled,
+}
+
+impl fmt::Debug for StepStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            StepStatus::Ok => "OK",
+            StepStatus::Nok => "NOK",
+            StepStatus::NotRun => "Did not run",
+            StepStatus::Failed => "Failed",
+            StepStatus::TimedOut => "Timed out",
+            StepStatus::Cancelled => "Cancelled",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for StepStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "OK" => Ok(StepStatus::Ok),
+            "NOK" => Ok(StepStatus::Nok),
+            "Did not run" => Ok(StepStatus::NotRun),
+            "Failed" => Ok(StepStatus::Failed),
+            "Timed out" => Ok(StepStatus::TimedOut),
+            "Cancelled" => Ok(StepStatus::Cancelled),
+            _ => Err(format!("Invalid step status: {}", s)),
+        }
+    }
+}
+
+/// Enum to represent agent message output type
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum StepOutputType {
+    Info,
+    Error,
+}
+
+impl fmt::Display for StepOutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let display = match self {
+            StepOutputType::Info => "I",
+            StepOutputType::Error => "E",
+        };
+        write!(f, "{}", display)
+    }
+}
+
+impl fmt::Debug for StepOutputType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match self {
+            StepOutputType::Info => "info",
+            StepOutputType::Error => "error",
+        };
+        write!(f, "{}", printable)
+    }
+}
+
+impl FromStr for StepOutputType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "I" => Ok(StepOutputType::Info),
+            "E" => Ok(StepOutputType::Error),
+            _ => Err(format!("Invalid output type: {}", s)),
+        }
+    }
+}
+
+/// Type of plan parameter:
+/// - String => Any text
+/// - Int => Whole number
+/// - Bool => true/false or yes/no
+#[derive(Eq, PartialEq, Clone)]
+pub enum ParamType {
+    String,
+    Int,
+    Bool,
+}
+
+impl fmt::Debug for ParamType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let printable = match *self {
+            ParamType::String => "string",
+            ParamType::Int => "int",
+            ParamType::Bool => "bool",
+        };
+        write!(f, "{}", printable)
+    }
+}
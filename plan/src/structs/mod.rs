@@ -0,0 +1,6 @@
+pub mod enums;
+pub mod plan;
+pub mod step;
+pub mod action;
+pub mod param;
+pub mod schedule;
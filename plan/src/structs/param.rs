@@ -0,0 +1,37 @@
+use super::enums::ParamType;
+
+/// Parameter of a plan, its value is specified when plan is executed
+/// - name => Name of parameter, it is referred as `{{name}}` in steps
+/// - param_type => Type of value, it is verified before the plan is run
+/// - default => Value if it is not specified, parameter without default is mandatory
+#[derive(Clone, Debug)]
+pub struct PlanParam {
+    pub name: String,
+    pub param_type: ParamType,
+    pub default: Option<String>,
+}
+
+impl PlanParam {
+    pub fn new(name: String) -> PlanParam {
+        PlanParam {
+            name,
+            param_type: ParamType::String,
+            default: None,
+        }
+    }
+
+    /// Verify that value is proper for the type of parameter
+    pub fn check_value(&self, value: &str) -> Result<(), String> {
+        let valid = match self.param_type {
+            ParamType::String => true,
+            ParamType::Int => value.parse::<i64>().is_ok(),
+            ParamType::Bool => ["true", "false", "yes", "no"].contains(&value),
+        };
+
+        if !valid {
+            return Err(format!("Value of parameter '{}' must be {:?}: {}", self.name, self.param_type, value));
+        }
+
+        Ok(())
+    }
+}
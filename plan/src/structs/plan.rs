@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::enums::{Concurrency, StepStatus};
+use super::param::PlanParam;
+use super::schedule::PlanSchedule;
+use super::step::Step;
+
+/// Prefix of the shell variables, which contain the parameter values in the command
+const PARAM_VAR_PREFIX: &str = "HEPHAESTUS_PARAM_";
+
+/// A plan consist of more step which can depend from each other
+/// - concurrency => How parallel runs of the plan, or of plans with the same lock, are handled
+/// - lock => Name of lock group, plans with the same lock do not run at the same time
+/// - kept_steps => Steps which are not run again at rerun, they keep their status of the original run
+pub struct Plan {
+    pub id: String,
+    pub status: StepStatus,
+    pub steps: Vec<Step>,
+    pub max_parallel: Option<usize>,
+    pub timeout: Option<Duration>,
+    pub params: Vec<PlanParam>,
+    pub schedule: Option<PlanSchedule>,
+    pub concurrency: Concurrency,
+    pub lock: Option<String>,
+    pub kept_steps: HashMap<String, StepStatus>,
+}
+
+impl Plan {
+    pub fn new(id: String, steps: Vec<Step>) -> Plan {
+        Plan { 
+            id, 
+            status: StepStatus::NotRun,
+            steps,
+            max_parallel: None,
+            timeout: None,
+            params: Vec::new(),
+            schedule: None,
+            concurrency: Concurrency::Allow,
+            lock: None,
+            kept_steps: HashMap::new(),
+        }
+    }
+
+    /// Indexes of steps in topological order: every step comes after its parents
+    ///
+    /// Steps which do not depend on each other keep their order of the file. Parents which do not exist
+    /// are ignored. Steps which are in a cycle, or depend on a cycle, are missing from the result.
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = Vec::new();
+        let mut placed: Vec<bool> = vec![false; self.steps.len()];
+
+        loop {
+            let next = (0..self.steps.len()).find(|index| {
+                !placed[*index] && self.steps[*index].parents.iter().all(|parent| {
+                    self.steps.iter().enumerate().all(|(i, step)| step.step_name != *parent || placed[i])
+                })
+            });
+
+            match next {
+                Some(index) => {
+                    placed[index] = true;
+                    order.push(index);
+                },
+                None => break,
+            }
+        }
+
+        order
+    }
+
+    /// Verify the specified parameter values and substitute them into the steps
+    ///
+    /// Unknown parameters, missing mandatory parameters and values with wrong type are rejected.
+    /// Every `{{name}}` is replaced in work directories, users and environment variables by the value.
+    /// Values are never put into the command as they are, because they would be interpreted by the shell:
+    /// command gets them as shell variables, which are assigned at the beginning of script with quoted values,
+    /// and `{{name}}` is replaced by the reference of the variable, e.g.: `${HEPHAESTUS_PARAM_version}`.
+    pub fn resolve_params(&mut self, values: &HashMap<String, String>) -> Result<(), String> {
+        let mut err_msg = String::new();
+
+        let mut unknown: Vec<&String> = values.keys()
+            .filter(|name| !self.params.iter().any(|p| &p.name == *name))
+            .collect();
+        unknown.sort();
+        for name in unknown {
+            err_msg += &format!("Unknown parameter: {}\n", name);
+        }
+
+        let mut resolved: HashMap<String, String> = HashMap::new();
+        for param in &self.params {
+            let value = match (values.get(&param.name), &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => default,
+                (None, None) => {
+                    err_msg += &format!("Parameter is mandatory: {}\n", param.name);
+                    continue;
+                }
+            };
+
+            if let Err(e) = param.check_value(value) {
+                err_msg += &format!("{}\n", e);
+                continue;
+            }
+
+            resolved.insert(param.name.clone(), value.clone());
+        }
+
+        if !err_msg.is_empty() {
+            return Err(err_msg);
+        }
+
+        for step in self.steps.iter_mut() {
+            if let Some(action) = &mut step.action {
+                let mut used: Vec<String> = Vec::new();
+                let cmd = substitute(&action.cmd, &resolved, |name, _| {
+                    if !used.iter().any(|u| u == name) {
+                        used.push(String::from(name));
+                    }
+                    format!("${{{}{}}}", PARAM_VAR_PREFIX, name)
+                });
+
+                used.sort();
+                let mut script = String::new();
+                for name in used {
+                    script += &format!("{}{}={}\n", PARAM_VAR_PREFIX, name, shell_quote(&resolved[&name]));
+                }
+                action.cmd = script + &cmd;
+
+                action.cwd = action.cwd.as_ref().map(|cwd| substitute(cwd, &resolved, |_, value| String::from(value)));
+            }
+
+            step.user = step.user.as_ref().map(|user| substitute(user, &resolved, |_, value| String::from(value)));
+
+            for value in step.envvars.values_mut() {
+                *value = substitute(value, &resolved, |_, value| String::from(value));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Replace the `{{name}}` references of parameters by the result of `replace`
+///
+/// Text is scanned once from left to right, so what is inserted is never scanned again, even if it contains
+/// `{{name}}` too. Other double braces, for example `{{.Names}}` of docker format, are kept as they are.
+fn substitute<F>(text: &str, values: &HashMap<String, String>, mut replace: F) -> String
+where F: FnMut(&str, &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        result += &rest[..start];
+        let after = &rest[start + 2..];
+
+        match after.find("}}").and_then(|end| values.get_key_value(&after[..end]).map(|pair| (end, pair))) {
+            Some((end, (name, value))) => {
+                result += &replace(name, value);
+                rest = &after[end + 2..];
+            },
+            None => {
+                result += "{{";
+                rest = after;
+            },
+        }
+    }
+
+    result += rest;
+    result
+}
+
+/// Quote the value as one word for the shell, single quotes within it are closed, escaped and opened again
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use crate::parser::parse_plan;
+
+    const PLAN: &str = r#"
+<plan id="deploy">
+<param name="version"/>
+<param name="count" type="int" default="1"/>
+<param name="force" type="bool" default="no"/>
+</plan>
+<step name="step01" desc="Deploy" cwd="/opt/{{version}}" user="app{{count}}" setenv="VERSION {{version}}">echo {{version}} {{count}} {{unknown}} {{.Names}}</step>
+"#;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (String::from(*name), String::from(*value))).collect()
+    }
+
+    /// Resolve the parameters of test plan and return the command, work directory, user and environment variable of its step
+    fn resolve(pairs: &[(&str, &str)]) -> Result<(String, Option<String>, Option<String>, String), String> {
+        let mut plan = parse_plan(PLAN, "deploy.conf").unwrap();
+        plan.resolve_params(&values(pairs))?;
+
+        let step = &plan.steps[0];
+        let action = step.action.as_ref().unwrap();
+        Ok((action.cmd.clone(), action.cwd.clone(), step.user.clone(), step.envvars["VERSION"].clone()))
+    }
+
+    #[test]
+    fn values_are_passed_as_shell_variables() {
+        let (cmd, cwd, user, env) = resolve(&[("version", "1.2")]).unwrap();
+
+        assert_eq!(cmd, "HEPHAESTUS_PARAM_count='1'\nHEPHAESTUS_PARAM_version='1.2'\necho ${HEPHAESTUS_PARAM_version} ${HEPHAESTUS_PARAM_count} {{unknown}} {{.Names}}");
+        assert_eq!(cwd.as_deref(), Some("/opt/1.2"));
+        assert_eq!(user.as_deref(), Some("app1"));
+        assert_eq!(env, "1.2");
+    }
+
+    #[test]
+    fn quoted_values_are_not_interpreted() {
+        assert_eq!(shell_quote("it's"), r#"'it'\''s'"#);
+        assert_eq!(shell_quote("a; rm -rf /"), "'a; rm -rf /'");
+        assert_eq!(shell_quote("$(whoami) `id`"), "'$(whoami) `id`'");
+
+        // Shell gets the value back as it was specified, reference is within double quotes like any shell variable with spaces
+        for value in ["it's", "a; echo injected", "$(echo injected)", "`echo injected`", "'; echo injected; '", "\"$HOME\""] {
+            let mut plan = parse_plan("<plan id=\"test\"><param name=\"value\"/></plan>\n<step name=\"s1\" desc=\"d\">printf %s \"{{value}}\"</step>", "test.conf").unwrap();
+            plan.resolve_params(&values(&[("value", value)])).unwrap();
+
+            let output = Command::new("bash").arg("-c").arg(&plan.steps[0].action.as_ref().unwrap().cmd).output().unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), value);
+        }
+    }
+
+    #[test]
+    fn substitution_is_single_pass() {
+        let (cmd, cwd, _, env) = resolve(&[("version", "{{count}}")]).unwrap();
+
+        assert!(cmd.starts_with("HEPHAESTUS_PARAM_count='1'\nHEPHAESTUS_PARAM_version='{{count}}'\n"), "{}", cmd);
+        assert_eq!(cwd.as_deref(), Some("/opt/{{count}}"));
+        assert_eq!(env, "{{count}}");
+
+        let values = values(&[("a", "{{b}}"), ("b", "x")]);
+        assert_eq!(substitute("{{a}}{{b}}", &values, |_, value| String::from(value)), "{{b}}x");
+    }
+
+    #[test]
+    fn unknown_placeholders_are_kept() {
+        let values = values(&[("a", "1")]);
+
+        assert_eq!(substitute("{{unknown}} {{ a }} {{a} {{.Names}} {{a}}", &values, |_, value| String::from(value)), "{{unknown}} {{ a }} {{a} {{.Names}} 1");
+        assert_eq!(substitute("{{{{a}}}}", &values, |_, value| String::from(value)), "{{1}}");
+        assert_eq!(substitute("end {{", &values, |_, value| String::from(value)), "end {{");
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert_eq!(resolve(&[]).unwrap_err(), "Parameter is mandatory: version\n");
+        assert_eq!(resolve(&[("version", "1"), ("verison", "2"), ("other", "3")]).unwrap_err(), "Unknown parameter: other\nUnknown parameter: verison\n");
+        assert_eq!(resolve(&[("version", "1"), ("count", "two"), ("force", "maybe")]).unwrap_err(),
+            "Value of parameter 'count' must be int: two\nValue of parameter 'force' must be bool: maybe\n");
+        assert!(resolve(&[("version", "1"), ("count", "-3"), ("force", "yes")]).is_ok());
+    }
+}
@@ -0,0 +1,321 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+
+use super::enums::Overlap;
+
+/// How many days are checked at most to find the next fire time, e.g. `0 0 29 2 *` fires only in leap years
+const MAX_SEARCH_DAYS: i64 = 366 * 8;
+
+const MONTH_NAMES: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Cron expression with the usual 5 fields: minute, hour, day of month, month and day of week
+///
+/// Every field is stored as a bit mask of the allowed values. Like in cron, if both day of month and
+/// day of week are restricted (they do not begin with `*`), the day matches when any of them matches.
+#[derive(Clone)]
+pub struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronExpr {
+    /// Parse an expression, e.g.: `0 3 * * *`, `*/15 8-18 * * mon-fri` or `@daily`
+    pub fn parse(text: &str) -> Result<CronExpr, String> {
+        let text = match text.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("schedule must have 5 fields (minute hour day month weekday): {}", text));
+        }
+
+        let weekdays = parse_field(fields[4], 0, 7, &WEEKDAY_NAMES, "weekday")?;
+
+        Ok(CronExpr {
+            minutes: parse_field(fields[0], 0, 59, &[], "minute")?,
+            hours: parse_field(fields[1], 0, 23, &[], "hour")?,
+            days: parse_field(fields[2], 1, 31, &[], "day")?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES, "month")?,
+            // Both 0 and 7 mean Sunday
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            // Like in cron, field is unrestricted if it begins with `*`, e.g.: `*/2` too
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    /// Return with the first fire time which is after the specified time
+    ///
+    /// Times which do not exist due to daylight saving are skipped, ambiguous times are fired only once.
+    pub fn next_after<T: TimeZone>(&self, after: &DateTime<T>) -> Option<DateTime<T>> {
+        let tz = after.timezone();
+        let mut from = after.naive_local();
+
+        for _ in 0..1000 {
+            let next = self.next_naive(from)?;
+            match tz.from_local_datetime(&next).earliest() {
+                Some(time) if time > *after => return Some(time),
+                _ => from = next,
+            }
+        }
+
+        None
+    }
+
+    /// Return with the first matching minute after the specified local time
+    fn next_naive(&self, from: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = from.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date();
+
+        for day in 0..MAX_SEARCH_DAYS {
+            if self.matches_day(date) {
+                let first_hour = if day == 0 { start.hour() } else { 0 };
+
+                for hour in first_hour..24 {
+                    if !bit(self.hours, hour) {
+                        continue;
+                    }
+
+                    let first_minute = if day == 0 && hour == start.hour() { start.minute() } else { 0 };
+
+                    for minute in first_minute..60 {
+                        if bit(self.minutes, minute) {
+                            return date.and_hms_opt(hour, minute, 0);
+                        }
+                    }
+                }
+            }
+
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !bit(self.months, date.month()) {
+            return false;
+        }
+
+        let day = bit(self.days, date.day());
+        let weekday = bit(self.weekdays, date.weekday().num_days_from_sunday());
+
+        // Unrestricted field matches every day, so only the other one decides, but with its steps, e.g.: `*/2`
+        match self.any_day || self.any_weekday {
+            true => day && weekday,
+            false => day || weekday,
+        }
+    }
+}
+
+/// Schedule of a plan
+/// - expression => Cron expression as it is written in the plan
+/// - timezone => Timezone of the expression, server's local time is used if it is not specified
+/// - overlap => What to do if the previous run is still running
+#[derive(Clone)]
+pub struct PlanSchedule {
+    pub expression: String,
+    pub cron: CronExpr,
+    pub timezone: Option<Tz>,
+    pub overlap: Overlap,
+}
+
+impl PlanSchedule {
+    pub fn new(expression: String, cron: CronExpr) -> PlanSchedule {
+        PlanSchedule {
+            expression,
+            cron,
+            timezone: None,
+            overlap: Overlap::Skip,
+        }
+    }
+
+    /// Next fire time after the specified time
+    pub fn next_after(&self, after: &DateTime<Local>) -> Option<DateTime<Local>> {
+        match &self.timezone {
+            Some(tz) => self.cron.next_after(&after.with_timezone(tz)).map(|time| time.with_timezone(&Local)),
+            None => self.cron.next_after(after),
+        }
+    }
+
+    /// Name of timezone, it is `local` if server's local time is used
+    pub fn timezone_name(&self) -> String {
+        match &self.timezone {
+            Some(tz) => tz.name().to_string(),
+            None => String::from("local"),
+        }
+    }
+}
+
+fn bit(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Parse one field of cron expression into a bit mask
+///
+/// Field is a comma separated list of `*`, numbers, names and ranges, each of them can have a step, e.g.: `*/5`, `1-10/2`.
+fn parse_field(text: &str, min: u32, max: u32, names: &[&str], field: &str) -> Result<u64, String> {
+    let mut mask: u64 = 0;
+
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step in {} field: {}", field, part)),
+            },
+            None => (part, 1),
+        };
+
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (parse_value(first, min, max, names, field)?, parse_value(last, min, max, names, field)?),
+                // Single value with step means from that value until the end, e.g.: `5/15`
+                None if part.contains('/') => (parse_value(range, min, max, names, field)?, max),
+                None => {
+                    let value = parse_value(range, min, max, names, field)?;
+                    (value, value)
+                },
+            },
+        };
+
+        if first > last {
+            return Err(format!("invalid range in {} field: {}", field, part));
+        }
+
+        let mut value = first;
+        while value <= last {
+            mask |= 1 << value;
+            value += step;
+        }
+    }
+
+    Ok(mask)
+}
+
+fn parse_value(text: &str, min: u32, max: u32, names: &[&str], field: &str) -> Result<u32, String> {
+    let lower = text.to_lowercase();
+    if let Some(index) = names.iter().position(|name| *name == lower) {
+        // Month names start from 1, weekday names from 0
+        return Ok(index as u32 + min);
+    }
+
+    match text.parse::<u32>() {
+        Ok(value) if value >= min && value <= max => Ok(value),
+        _ => Err(format!("invalid value in {} field, it must be between {} and {}: {}", field, min, max, text)),
+    }
+}
+
+/// A plan which is registered in the scheduler
+/// - next => When the plan is started next time, None if schedule never fires again
+/// - queued => Plan should have been started, but it is waiting for the end of previous run
+#[derive(Clone)]
+pub struct ScheduledPlan {
+    pub set: String,
+    pub plan: String,
+    pub schedule: PlanSchedule,
+    pub next: Option<DateTime<Local>>,
+    pub queued: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(tz: Tz, text: &str) -> DateTime<Tz> {
+        let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M").unwrap();
+        tz.from_local_datetime(&time).earliest().unwrap()
+    }
+
+    fn next(expression: &str, after: &DateTime<Tz>) -> String {
+        let cron = CronExpr::parse(expression).unwrap();
+        cron.next_after(after).unwrap().format("%Y-%m-%d %H:%M %:z").to_string()
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(CronExpr::parse("0 0 * *").is_err());
+        assert!(CronExpr::parse("0 0 * * * *").is_err());
+        assert!(CronExpr::parse("60 * * * *").is_err());
+        assert!(CronExpr::parse("* 24 * * *").is_err());
+        assert!(CronExpr::parse("* * 0 * *").is_err());
+        assert!(CronExpr::parse("* * * 13 *").is_err());
+        assert!(CronExpr::parse("* * * * 8").is_err());
+        assert!(CronExpr::parse("*/0 * * * *").is_err());
+        assert!(CronExpr::parse("5-1 * * * *").is_err());
+        assert!(CronExpr::parse("* * * foo *").is_err());
+        assert!(CronExpr::parse("@often").is_err());
+
+        assert!(CronExpr::parse("0,30 8-18/2 1-15 jan-jun MON-fri").is_ok());
+        assert!(CronExpr::parse("5/15 * * * *").is_ok());
+        assert!(CronExpr::parse("@annually").is_ok());
+    }
+
+    #[test]
+    fn next_fire_time() {
+        let tz = chrono_tz::UTC;
+
+        assert_eq!(next("*/15 * * * *", &time(tz, "2024-01-01 10:07")), "2024-01-01 10:15 +00:00");
+        assert_eq!(next("*/15 * * * *", &time(tz, "2024-01-01 10:15")), "2024-01-01 10:30 +00:00");
+        assert_eq!(next("5/20 * * * *", &time(tz, "2024-01-01 10:50")), "2024-01-01 11:05 +00:00");
+        assert_eq!(next("0 3 * * *", &time(tz, "2024-01-01 03:00")), "2024-01-02 03:00 +00:00");
+        assert_eq!(next("@hourly", &time(tz, "2024-12-31 23:59")), "2025-01-01 00:00 +00:00");
+        assert_eq!(next("@monthly", &time(tz, "2024-01-31 12:00")), "2024-02-01 00:00 +00:00");
+        assert_eq!(next("0 0 29 2 *", &time(tz, "2024-03-01 00:00")), "2028-02-29 00:00 +00:00");
+    }
+
+    #[test]
+    fn day_and_weekday() {
+        let tz = chrono_tz::UTC;
+
+        // 2024-01-01 is Monday, 7 is Sunday too
+        assert_eq!(next("0 0 * * 7", &time(tz, "2024-01-01 00:00")), "2024-01-07 00:00 +00:00");
+        assert_eq!(next("0 0 * * sun", &time(tz, "2024-01-01 00:00")), "2024-01-07 00:00 +00:00");
+        assert_eq!(next("0 0 * * mon-fri", &time(tz, "2024-01-05 12:00")), "2024-01-08 00:00 +00:00");
+
+        // Both are restricted: any of them can match
+        assert_eq!(next("0 0 13 * fri", &time(tz, "2024-01-01 00:00")), "2024-01-05 00:00 +00:00");
+        assert_eq!(next("0 0 2 * fri", &time(tz, "2024-01-01 00:00")), "2024-01-02 00:00 +00:00");
+
+        // Field beginning with `*` is unrestricted, but its steps still apply
+        assert_eq!(next("0 0 */2 * mon", &time(tz, "2024-01-01 00:00")), "2024-01-15 00:00 +00:00");
+        assert_eq!(next("0 0 1 * */2", &time(tz, "2024-01-01 00:00")), "2024-02-01 00:00 +00:00");
+    }
+
+    #[test]
+    fn daylight_saving_gap() {
+        // 2024-03-31 02:00 => 03:00 in Budapest, so 02:30 does not exist on that day
+        let tz = chrono_tz::Europe::Budapest;
+
+        assert_eq!(next("30 2 * * *", &time(tz, "2024-03-30 03:00")), "2024-04-01 02:30 +02:00");
+        assert_eq!(next("*/30 * * * *", &time(tz, "2024-03-31 01:45")), "2024-03-31 03:00 +02:00");
+    }
+
+    #[test]
+    fn daylight_saving_overlap() {
+        // 2024-10-27 03:00 => 02:00 in Budapest, so 02:00-02:59 happens twice, but fires only once
+        let tz = chrono_tz::Europe::Budapest;
+
+        let first = CronExpr::parse("30 2 * * *").unwrap().next_after(&time(tz, "2024-10-26 12:00")).unwrap();
+        assert_eq!(first.format("%Y-%m-%d %H:%M %:z").to_string(), "2024-10-27 02:30 +02:00");
+        assert_eq!(next("30 2 * * *", &first), "2024-10-28 02:30 +01:00");
+
+        assert_eq!(next("*/30 * * * *", &time(tz, "2024-10-27 02:30")), "2024-10-27 03:00 +01:00");
+
+        let repeated = tz.from_local_datetime(&NaiveDateTime::parse_from_str("2024-10-27 02:15", "%Y-%m-%d %H:%M").unwrap()).latest().unwrap();
+        assert_eq!(repeated.format("%:z").to_string(), "+01:00");
+        assert_eq!(next("*/30 * * * *", &repeated), "2024-10-27 03:00 +01:00");
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+use std::process::{Child, Command, ExitStatus};
+use std::path::Path;
+use std::io::{Read, BufReader, BufRead};
+use std::process::Stdio;
+use std::os::unix::process::CommandExt;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::enums::{JoinMode, StepStatus, StepType, StepOutputType};
+use super::action::Action;
+
+use chrono::{DateTime, Local};
+
+#[derive(Clone)]
+pub struct Step {
+    pub step_name: String,
+    pub description: String,
+    pub step_type: StepType,
+    pub user: Option<String>,
+    pub action: Option<Action>,
+    pub parents: Vec<String>,
+    pub join: JoinMode,
+    pub status: StepStatus,
+    pub envvars: HashMap<String, String>,
+    pub timeout: Option<Duration>,
+    pub retry: u32,
+    pub retry_delay: Duration,
+    pub retry_backoff: u32,
+    pub attempt: u32,
+    pub exit_code: Option<i32>,
+}
+
+/// How long a process group has to stop after SIGTERM, before it is killed by SIGKILL
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Longest delay between two attempts, backoff does not increase the delay over it
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Most attempts which can be made after the first one
+pub const MAX_RETRY: u32 = 100;
+
+/// Biggest multiplier of retry delay
+pub const MAX_RETRY_BACKOFF: u32 = 10;
+
+impl Step {
+    /// Create new empty step
+    /// 
+    /// It does not requires any input, but if data is not filled up, it would fail on validate process
+    pub fn new_empty() -> Step {
+        Step {
+            step_name: String::new(),
+            description: String::new(),
+            step_type: StepType::None,
+            user: None,
+            action: None,
+            parents: Vec::new(),
+            join: JoinMode::AllOk,
+            status: StepStatus::NotRun,
+            envvars: HashMap::new(),
+            timeout: None,
+            retry: 0,
+            retry_delay: Duration::from_secs(0),
+            retry_backoff: 1,
+            attempt: 0,
+            exit_code: None,
+        }
+    }
+
+    /// Validate step
+    /// 
+    /// Be assumed that the specified step is okay and correct to run it, it has every mandatory data
+    pub fn validate(&self) -> Result<(), String> {
+        let mut err_msg: String = String::new();
+
+        if self.step_name.is_empty() {
+            err_msg += "Step name cannot be empty!\n";
+        }
+
+        if self.description.is_empty() {
+            err_msg += "Description cannot be empty!\n";
+        }
+
+        if self.step_type == StepType::None {
+            err_msg += "Step type must be specified!\n";
+        }
+
+        if self.step_type == StepType::Approval {
+            if self.action.is_some() {
+                err_msg += "Approval step cannot have command!\n";
+            }
+        }
+        else if self.action.is_none() {
+            err_msg += "Action must be specified!\n";
+        }
+
+        if self.step_type == StepType::Recovery && self.parents.is_empty() {
+            err_msg += "Recovery step must have parent!\n";
+        }
+
+        if self.step_type != StepType::Action && self.step_type != StepType::Approval && self.join != JoinMode::AllOk {
+            err_msg += "Join mode can be specified only for regular and approval step!\n";
+        }
+        
+        if err_msg.is_empty() {
+            return Ok(());
+        }
+        Err(err_msg)
+    }
+
+    /// Execute the command from the step and change its status accordingly
+    ///
+    /// Every line, what command writes, is passed to `output` as soon as it is read.
+    /// Command is killed when its own timeout or the specified deadline (e.g.: timeout of plan) has expired,
+    /// or when the cancel flag is set. If command is ended with non-zero exit code and retry is set,
+    /// command is executed again after the retry delay, which is multiplied by backoff after every attempt.
+    pub fn execute<F>(&mut self, deadline: Option<Instant>, cancel: &AtomicBool, output: F)
+    where F: Fn(StepOutput) + Sync {
+        let message = |text: String, out_type: StepOutputType| {
+            output(StepOutput {
+                time: time_is_now(),
+                text,
+                out_type,
+            });
+        };
+
+        let attempts = self.retry + 1;
+        let mut delay = self.retry_delay;
+
+        for attempt in 1..=attempts {
+            self.attempt = attempt;
+
+            if attempts > 1 {
+                message(format!("----> Attempt {}/{} is started", attempt, attempts), StepOutputType::Info);
+            }
+
+            self.run_once(deadline, cancel, &output);
+
+            if self.status != StepStatus::Nok || attempt == attempts {
+                break;
+            }
+
+            message(format!("----> Attempt {}/{} has failed, retry in {:?}", attempt, attempts, delay), StepOutputType::Error);
+
+            if let Some(reason) = wait_delay(delay, deadline, cancel) {
+                let text = match reason {
+                    StepStatus::Cancelled => "----> Retry is stopped because plan has been cancelled",
+                    _ => "----> Retry is stopped because deadline has expired",
+                };
+                self.status = reason;
+                message(String::from(text), StepOutputType::Error);
+                break;
+            }
+
+            delay = delay.checked_mul(self.retry_backoff).map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY));
+        }
+    }
+
+    /// Execute the command from the step once
+    fn run_once<F>(&mut self, deadline: Option<Instant>, cancel: &AtomicBool, output: &F)
+    where F: Fn(StepOutput) + Sync {
+        let message = |text: String, out_type: StepOutputType| {
+            output(StepOutput {
+                time: time_is_now(),
+                text,
+                out_type,
+            });
+        };
+
+        self.exit_code = None;
+
+        if cancel.load(Ordering::SeqCst) {
+            self.status = StepStatus::Cancelled;
+            message(String::from("----> Step is not started because plan has been cancelled"), StepOutputType::Error);
+            return;
+        }
+
+        let deadline = match (self.timeout, deadline) {
+            (Some(timeout), Some(deadline)) => Some(std::cmp::min(Instant::now() + timeout, deadline)),
+            (Some(timeout), None) => Some(Instant::now() + timeout),
+            (None, deadline) => deadline,
+        };
+
+        if let Some(deadline) = deadline {
+            if deadline <= Instant::now() {
+                self.status = StepStatus::TimedOut;
+                message(String::from("----> Step is not started because its deadline has already expired"), StepOutputType::Error);
+                return;
+            }
+        }
+
+        let act = match &self.action {
+            Some(act) => act,
+            None => {
+                self.status = StepStatus::Nok;
+                return;
+            }
+        };
+
+        if act.cmd.trim().is_empty() {
+            self.status = StepStatus::Failed;
+            message("Command is not specified".to_string(), StepOutputType::Error);
+            return;
+        }
+
+        // Prepare command
+        let mut cmd: Command = match &self.user {
+            Some(u) => {
+                let mut b_cmd = Command::new("/usr/bin/sudo");
+                b_cmd.arg("-u");
+                b_cmd.arg(u);
+                b_cmd.arg("bash");
+                b_cmd.arg("-c");
+                b_cmd.arg(&act.cmd);
+                b_cmd
+            },
+            None => {
+                let mut b_cmd = Command::new("bash");
+                b_cmd.arg("-c");
+                b_cmd.arg(&act.cmd);
+                b_cmd
+            },
+        };
+
+        if !self.envvars.is_empty() {
+            for (key, value) in &self.envvars {
+                cmd.env(key, value);
+            }
+        }
+
+        if let Some(cwd) = &act.cwd {
+            let path = Path::new(cwd);
+            if !path.exists() {
+                self.status = StepStatus::Failed;
+                message(format!("Work directory does not exist: {}", path.display()), StepOutputType::Error);
+                return;
+            }
+            cmd.current_dir(path);
+        }
+
+        // Because accurate timestamp is needed, what the program wrote and when, this child has to be spawn
+        // Outputs are directed to pipes, which is conitnousily read later
+        // Child is put into its own process group, so in case of timeout or cancel, every process can be killed what it started
+        let mut child = match cmd
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                self.status = StepStatus::Failed;
+                message(format!("----> Failed to start command: {:?}", e), StepOutputType::Error);
+                return;
+            }
+        };
+
+        let mut stopped: Option<StepStatus> = None;
+
+        // Connect to child outputs and forward them continousily, meanwhile watch the deadline and cancel flag
+        let status = std::thread::scope(|spawner| {
+            let stdout_pipe = child.stdout.take();
+            let stderr_pipe = child.stderr.take();
+            spawner.spawn(move || {
+                if let Some(pipe) = stdout_pipe {
+                    read_buffer(&mut BufReader::new(pipe), StepOutputType::Info, output);
+                }
+            });
+            spawner.spawn(move || {
+                if let Some(pipe) = stderr_pipe {
+                    read_buffer(&mut BufReader::new(pipe), StepOutputType::Error, output);
+                }
+            });
+
+            wait_child(&mut child, deadline, cancel, &mut stopped)
+        });
+
+        if let Some(reason) = stopped {
+            let text = match reason {
+                StepStatus::Cancelled => "----> Step is cancelled, its process group has been killed",
+                _ => "----> Step is timed out, its process group has been killed",
+            };
+            self.status = reason;
+            message(String::from(text), StepOutputType::Error);
+            return;
+        }
+
+        match status {
+            Ok(code) => {
+                self.exit_code = code.code();
+                if code.success() {
+                    self.status = StepStatus::Ok;
+                    message(String::from("----> Step is ended with exit code 0"), StepOutputType::Info);
+                }
+                else {
+                    self.status = StepStatus::Nok;
+                    message(format!("----> Step is ended with exit code {:?}", code.code()), StepOutputType::Error);
+                }
+            },
+            Err(e) => {
+                self.status = StepStatus::Failed;
+                message(format!("----> Step is failed: {:?}", e), StepOutputType::Error);
+            },
+        }
+    }
+}
+
+/// Wait before the next attempt
+///
+/// Waiting is interrupted if plan is cancelled or deadline has expired, then the reason is returned.
+fn wait_delay(delay: Duration, deadline: Option<Instant>, cancel: &AtomicBool) -> Option<StepStatus> {
+    let end = Instant::now() + delay;
+
+    loop {
+        if cancel.load(Ordering::SeqCst) {
+            return Some(StepStatus::Cancelled);
+        }
+
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                return Some(StepStatus::TimedOut);
+            }
+        }
+
+        let now = Instant::now();
+        if now >= end {
+            return None;
+        }
+
+        std::thread::sleep(std::cmp::min(end - now, Duration::from_millis(100)));
+    }
+}
+
+/// Wait for child to end
+///
+/// If deadline expires or cancel flag is set, whole process group of child is terminated: first with SIGTERM,
+/// then if it is still alive after the grace period, with SIGKILL. Reason of stop is written into `stopped`.
+fn wait_child(child: &mut Child, deadline: Option<Instant>, cancel: &AtomicBool, stopped: &mut Option<StepStatus>) -> std::io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if cancel.load(Ordering::SeqCst) {
+            *stopped = Some(StepStatus::Cancelled);
+            break;
+        }
+
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                *stopped = Some(StepStatus::TimedOut);
+                break;
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let pgid = child.id() as libc::pid_t;
+
+    unsafe { libc::kill(-pgid, libc::SIGTERM); }
+
+    let grace_end = Instant::now() + KILL_GRACE_PERIOD;
+    while Instant::now() < grace_end {
+        if let Some(status) = child.try_wait()? {
+            // Leader has stopped, but other member of group might still be alive
+            unsafe { libc::kill(-pgid, libc::SIGKILL); }
+            return Ok(status);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    unsafe { libc::kill(-pgid, libc::SIGKILL); }
+    child.wait()
+}
+
+pub struct StepOutput {
+    pub time: DateTime<Local>,
+    pub text: String,
+    pub out_type: StepOutputType,
+}
+
+// Internal function, it is used to read the stdout and stderr of agent and forward every line immediately
+fn read_buffer<T: Read, F: Fn(StepOutput)>(reader: &mut BufReader<T>, out_type: StepOutputType, output: &F) {
+    let mut line = String::new();
+
+    while let Ok(size) = reader.read_line(&mut line) {
+        if size == 0 {
+            break;
+        }
+
+        output(StepOutput {
+            time: time_is_now(),
+            text: line.replace("\n", ""),
+            out_type
+        });
+
+        line = String::new();
+    }
+}
+
+fn time_is_now() -> DateTime<Local> {
+    Local::now()
+}
\ No newline at end of file
@@ -0,0 +1,402 @@
+//! Parser of TOML and YAML plan files
+//!
+//! These formats are easier to generate by programs than the tag format. Attributes of plan are the top level
+//! keys, parameters and steps are listed in `params` and `steps`, with the same attributes like their tags.
+//! Kind of step is specified by `type` (`action` by default), its command by `action`. Parents can be listed
+//! in an array and environment variables are specified as a table, e.g. in TOML:
+//! ```toml
+//! id = "backup"
+//!
+//! [[steps]]
+//! name = "step01"
+//! desc = "Create backup"
+//! parent = ["step00"]
+//! setenv = { TARGET = "/backup" }
+//! action = "tar -czf $TARGET/etc.tar.gz /etc"
+//! ```
+//! Every value is checked the same way like in the tag format. Their errors are reported without location,
+//! because it is not known after deserialization, but with the name of step.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::structs::action::Action;
+use crate::structs::enums::StepType;
+use crate::structs::plan::Plan;
+use crate::structs::step::Step;
+
+use super::lexer::{Location, ParseError};
+use super::parser::{add_params, check_graph, param_attributes, plan_attributes, step_attribute, Attribute, ParentRef};
+
+/// Plan as it is written in the file, attributes are processed by the parser of tag format
+#[derive(Deserialize)]
+struct PlanDocument {
+    #[serde(default)]
+    params: Vec<BTreeMap<String, Scalar>>,
+    #[serde(default)]
+    steps: Vec<StepDocument>,
+    #[serde(flatten)]
+    attrs: BTreeMap<String, Scalar>,
+}
+
+/// Step as it is written in the file, attributes which can be specified more times have their own type
+#[derive(Deserialize)]
+struct StepDocument {
+    #[serde(rename = "type")]
+    step_type: Option<String>,
+    action: Option<String>,
+    parent: Option<Names>,
+    #[serde(default)]
+    setenv: BTreeMap<String, String>,
+    #[serde(flatten)]
+    attrs: BTreeMap<String, Scalar>,
+}
+
+/// Value of an attribute, numbers and booleans can be written without quotes
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "value must be a string, number or boolean")]
+enum Scalar {
+    Text(String),
+    Number(i64),
+    Flag(bool),
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scalar::Text(text) => write!(f, "{}", text),
+            Scalar::Number(number) => write!(f, "{}", number),
+            Scalar::Flag(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+/// Parents of step: one name (or more separated by comma) or an array of names
+#[derive(Deserialize)]
+#[serde(untagged, expecting = "parent must be a step name or an array of step names")]
+enum Names {
+    One(String),
+    More(Vec<String>),
+}
+
+/// Process the content of a TOML plan file, errors are collected into `errors`
+pub fn parse_toml(content: &str, errors: &mut Vec<ParseError>) -> Option<Plan> {
+    match toml::from_str::<PlanDocument>(content) {
+        Ok(document) => Some(build_plan(document, errors)),
+        Err(e) => {
+            let location = e.span().map_or(Location::NONE, |span| location_of(content, span.start));
+            errors.push(ParseError::new(location, String::from(e.message().trim_end())));
+            None
+        },
+    }
+}
+
+/// Process the content of a YAML plan file, errors are collected into `errors`
+pub fn parse_yaml(content: &str, errors: &mut Vec<ParseError>) -> Option<Plan> {
+    match serde_yaml::from_str::<PlanDocument>(content) {
+        Ok(document) => Some(build_plan(document, errors)),
+        Err(e) => {
+            let message = e.to_string();
+
+            // Location is written into the message, but it is reported in the same way like at other formats.
+            // Message can contain the location of context too, e.g.: `..., while parsing a block mapping at line 2 column 3`,
+            // so only the location of error is removed.
+            let (location, message) = match e.location() {
+                Some(location) => {
+                    let mark = format!(" at line {} column {}", location.line(), location.column());
+                    (Location { line: location.line(), col: location.column() }, message.replacen(&mark, "", 1))
+                },
+                None => (Location::NONE, message),
+            };
+            errors.push(ParseError::new(location, message));
+            None
+        },
+    }
+}
+
+/// Line and column of a byte offset in the content
+fn location_of(content: &str, offset: usize) -> Location {
+    let before = &content[..offset.min(content.len())];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    Location {
+        line: before.matches('\n').count() + 1,
+        col: before[line_start..].chars().count() + 1,
+    }
+}
+
+/// Create the plan from the deserialized document, values are checked by the parser of tag format
+fn build_plan(document: PlanDocument, errors: &mut Vec<ParseError>) -> Plan {
+    let (mut plan, schedule_location) = plan_attributes(to_attrs(document.attrs), Location::NONE, errors);
+
+    let params = document.params.into_iter()
+        .map(|attrs| (param_attributes(to_attrs(attrs), Location::NONE, errors), Location::NONE))
+        .collect();
+    add_params(&mut plan, params, schedule_location, errors);
+
+    let mut parent_refs: Vec<ParentRef> = Vec::new();
+
+    for (index, document) in document.steps.into_iter().enumerate() {
+        let step = build_step(index, document, errors);
+
+        for parent in &step.parents {
+            parent_refs.push((index, parent.clone(), Location::NONE));
+        }
+        plan.steps.push(step);
+    }
+
+    let step_locations = vec![Location::NONE; plan.steps.len()];
+    check_graph(&plan, &step_locations, &parent_refs, errors);
+
+    plan
+}
+
+/// Create a step from the deserialized document, errors are prefixed by the name of step
+fn build_step(index: usize, document: StepDocument, errors: &mut Vec<ParseError>) -> Step {
+    let mut step = Step::new_empty();
+    let mut step_errors: Vec<ParseError> = Vec::new();
+
+    // Type decides which attributes can be used, so it is processed first
+    step.step_type = match document.step_type.as_deref() {
+        None | Some("action") => StepType::Action,
+        Some("recovery") => StepType::Recovery,
+        Some("finally") => StepType::Finally,
+        Some("approval") => StepType::Approval,
+        Some(other) => {
+            step_errors.push(ParseError::new(Location::NONE, format!("type must be action, recovery, finally or approval: {}", other)));
+            StepType::Action
+        },
+    };
+
+    let mut cwd: Option<String> = None;
+
+    for (name, value, location) in to_attrs(document.attrs) {
+        step_attribute(&mut step, &name, value, location, &mut cwd, &mut step_errors);
+    }
+
+    let parents = match document.parent {
+        Some(Names::One(name)) => vec![name],
+        Some(Names::More(names)) => names,
+        None => Vec::new(),
+    };
+    for parent in parents {
+        step_attribute(&mut step, "parent", parent, Location::NONE, &mut cwd, &mut step_errors);
+    }
+
+    for (key, value) in document.setenv {
+        if key.contains(char::is_whitespace) {
+            step_errors.push(ParseError::new(Location::NONE, format!("name of environment variable cannot contain space: '{}'", key)));
+            continue;
+        }
+        step_attribute(&mut step, "setenv", format!("{} {}", key, value), Location::NONE, &mut cwd, &mut step_errors);
+    }
+
+    // Leading and trailing empty lines are just formatting, like at the tag format
+    if let Some(action) = document.action {
+        let action = action.trim_start_matches(['\n', '\r']).trim_end();
+        if !action.is_empty() {
+            step.action = Some(Action::new(String::from(action), cwd));
+        }
+    }
+
+    if let Err(e) = step.validate() {
+        for line in e.lines() {
+            step_errors.push(ParseError::new(Location::NONE, String::from(line)));
+        }
+    }
+
+    let label = match step.step_name.is_empty() {
+        true => format!("step #{}", index + 1),
+        false => format!("step {}", step.step_name),
+    };
+    for e in step_errors {
+        errors.push(ParseError::new(e.location, format!("{}: {}", label, e.message)));
+    }
+
+    step
+}
+
+/// Convert the values to attributes of tag format, they have no location
+fn to_attrs(attrs: BTreeMap<String, Scalar>) -> Vec<Attribute> {
+    attrs.into_iter()
+        .map(|(name, value)| (name, value.to_string(), Location::NONE))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tests::{assert_same_plan, errors};
+
+    const TAG: &str = r#"
+<plan id="backup" timeout="1h" max_parallel="2">
+<param name="target" default="/backup"/>
+<param name="level" type="int" default="9"/>
+</plan>
+<step name="step01" desc="Create backup" setenv="TARGET {{target}}" setenv="LEVEL {{level}}" retry="2">
+mkdir -p $TARGET
+tar -czf $TARGET/etc.tar.gz /etc
+</step>
+<step name="step02" desc="Check backup" parent="step01" join="all_done" timeout="5m">ls -l $TARGET</step>
+<recovery name="fail01" desc="Report failure" parent="step01,step02">echo failed</recovery>
+<approval name="approve" desc="Approve cleanup" parent="step02"/>
+"#;
+
+    const TOML: &str = r#"
+id = "backup"
+timeout = "1h"
+max_parallel = 2
+
+[[params]]
+name = "target"
+default = "/backup"
+
+[[params]]
+name = "level"
+type = "int"
+default = 9
+
+[[steps]]
+name = "step01"
+desc = "Create backup"
+setenv = { TARGET = "{{target}}", LEVEL = "{{level}}" }
+retry = 2
+action = """
+mkdir -p $TARGET
+tar -czf $TARGET/etc.tar.gz /etc
+"""
+
+[[steps]]
+name = "step02"
+desc = "Check backup"
+parent = "step01"
+join = "all_done"
+timeout = "5m"
+action = "ls -l $TARGET"
+
+[[steps]]
+name = "fail01"
+type = "recovery"
+desc = "Report failure"
+parent = ["step01", "step02"]
+action = "echo failed"
+
+[[steps]]
+name = "approve"
+type = "approval"
+desc = "Approve cleanup"
+parent = "step02"
+"#;
+
+    const YAML: &str = r#"
+id: backup
+timeout: 1h
+max_parallel: 2
+params:
+  - name: target
+    default: /backup
+  - name: level
+    type: int
+    default: 9
+steps:
+  - name: step01
+    desc: Create backup
+    setenv:
+      TARGET: "{{target}}"
+      LEVEL: "{{level}}"
+    retry: 2
+    action: |
+      mkdir -p $TARGET
+      tar -czf $TARGET/etc.tar.gz /etc
+  - name: step02
+    desc: Check backup
+    parent: step01
+    join: all_done
+    timeout: 5m
+    action: ls -l $TARGET
+  - name: fail01
+    type: recovery
+    desc: Report failure
+    parent: [step01, step02]
+    action: echo failed
+  - name: approve
+    type: approval
+    desc: Approve cleanup
+    parent: step02
+"#;
+
+    #[test]
+    fn same_plan_as_tag_format() {
+        assert_same_plan(TOML, "backup.toml", TAG);
+        assert_same_plan(YAML, "backup.yaml", TAG);
+    }
+
+    #[test]
+    fn location_of_offset() {
+        let content = "first\nsecönd\n\nlast";
+
+        assert_eq!((location_of(content, 0).line, location_of(content, 0).col), (1, 1));
+        assert_eq!((location_of(content, 3).line, location_of(content, 3).col), (1, 4));
+        assert_eq!((location_of(content, 6).line, location_of(content, 6).col), (2, 1));
+        // Column is counted in characters, not in bytes
+        assert_eq!((location_of(content, 11).line, location_of(content, 11).col), (2, 5));
+        assert_eq!((location_of(content, 14).line, location_of(content, 14).col), (3, 1));
+        assert_eq!((location_of(content, 100).line, location_of(content, 100).col), (4, 5));
+    }
+
+    #[test]
+    fn syntax_errors_have_location() {
+        let toml = errors("id = \"backup\"\n[[steps]]\nname = \n", "backup.toml");
+        assert_eq!(toml.len(), 1);
+        assert!(toml[0].starts_with("backup.toml:3:"), "{:?}", toml);
+
+        // Location of the error is removed from YAML message, it is reported like at the other formats
+        assert_eq!(errors("id: backup\nsteps:\n  - name: [1]\n", "backup.yaml"), vec![
+            "backup.yaml:3:5: steps[0]: value must be a string, number or boolean",
+        ]);
+        assert_eq!(errors("id: backup\nsteps:\n  - name: step01\n   desc: x\n", "backup.yaml"), vec![
+            "backup.yaml:4:4: did not find expected '-' indicator, while parsing a block collection at line 3 column 3",
+        ]);
+    }
+
+    #[test]
+    fn wrong_value_types() {
+        assert_eq!(errors("id = \"backup\"\n[[steps]]\nname = \"s\"\nparent = 1\n", "backup.toml").len(), 1);
+        assert_eq!(errors("id: backup\nsteps:\n  - name: s\n    retry: [1]\n", "backup.yaml").len(), 1);
+    }
+
+    #[test]
+    fn errors_are_labeled_with_step() {
+        let content = r#"
+id = "backup"
+
+[[steps]]
+desc = "No name"
+action = "echo 1"
+
+[[steps]]
+name = "step02"
+desc = "Wrong attributes"
+type = "script"
+retry = "many"
+setenv = { "MY VAR" = "1" }
+action = "echo 2"
+"#;
+
+        assert_eq!(errors(content, "backup.toml"), vec![
+            "backup.toml: step #1: Step name cannot be empty!",
+            "backup.toml: step step02: type must be action, recovery, finally or approval: script",
+            "backup.toml: step step02: retry must be a number between 0 and 100: many",
+            "backup.toml: step step02: name of environment variable cannot contain space: 'MY VAR'",
+        ]);
+    }
+
+    #[test]
+    fn unknown_parent() {
+        let content = "id: backup\nsteps:\n  - name: s1\n    desc: d\n    parent: [s0]\n    action: echo\n";
+
+        assert_eq!(errors(content, "backup.yaml"), vec!["backup.yaml: parent of s1 does not exist: s0"]);
+    }
+}